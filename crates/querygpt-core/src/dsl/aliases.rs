@@ -0,0 +1,59 @@
+use std::collections::BTreeSet;
+use crate::schema::cards::SchemaCards;
+
+/// Words an alias must not be, since `FROM t on` or `JOIN t as` would not parse.
+const RESERVED: &[&str] = &[
+    "all", "and", "any", "as", "asc", "at", "by", "case", "cast", "desc", "do", "else", "end", "false",
+    "for", "from", "group", "having", "if", "in", "into", "is", "join", "left", "limit", "not", "null",
+    "offset", "on", "or", "order", "select", "set", "table", "then", "to", "true", "union", "use", "when",
+    "where", "with",
+];
+
+/// Hands out table aliases for one query scope, never the same one twice.
+///
+/// A node gets its preferred alias when that is free, otherwise the first free
+/// `<preferred><n>` for n = 1, 2, ...; e.g. an EXISTS subquery on an entity that the outer
+/// query already aliases `op` gets `op1`. Allocation depends only on the preferred aliases
+/// and the order they are requested in, so the same plan always gets the same aliases.
+#[derive(Debug, Clone, Default)]
+pub struct AliasAllocator {
+    taken: BTreeSet<String>,
+}
+
+impl AliasAllocator {
+    /// An allocator for a scope nested in one that already uses `taken`.
+    pub fn nested<I, S>(taken: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self { taken: taken.into_iter().map(Into::into).collect() }
+    }
+
+    pub fn allocate(&mut self, preferred: &str) -> String {
+        let free = |alias: &String| !self.taken.contains(alias) && !RESERVED.contains(&alias.as_str());
+        let alias = std::iter::once(preferred.to_string())
+            .chain((1..).map(|n| format!("{}{}", preferred, n)))
+            .find(free)
+            .unwrap_or_default();
+        self.taken.insert(alias.clone());
+        alias
+    }
+}
+
+/// The alias a join graph node asks for: its entity card's `alias` for an entity, else the
+/// initials of the role or entity name (`parent_campaign` -> `pc`).
+pub fn preferred_alias(cards: &SchemaCards, node: &str) -> String {
+    let declared = cards
+        .entities
+        .iter()
+        .find(|e| e.name == node)
+        .and_then(|e| e.alias.clone());
+
+    declared.unwrap_or_else(|| {
+        node.split('_')
+            .filter_map(|word| word.chars().next())
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    })
+}
@@ -1,26 +1,82 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
-use crate::dsl::plan::{IntermediatePlan, JoinCondition, JoinType, PlanJoin, PlanTable};
-use crate::dsl::report_spec::ReportSpec;
-use crate::schema::cards::SchemaCards;
+use crate::dsl::plan::{IntermediatePlan, JoinCondition, JoinType, PlanJoin, PlanProfileScope, PlanTable};
+use crate::dsl::report_spec::{DeletedMode, Mode, ReportSpec};
+use crate::schema::cards::{DerivedField, EntityCard, FilterHint, JoinEdge, RowLimits, SchemaCards};
+use crate::schema::join_graph::{connecting_edges, path_to};
+use crate::schema::workspaces::workspace_schema;
+use crate::dsl::validate::check_report_spec;
+use crate::dsl::diagnostics::Diagnostic;
+use crate::dsl::join_rules::check_join_rules;
+use crate::dsl::aliases::{preferred_alias, AliasAllocator};
+use crate::dsl::fan_out::{check_fan_out, pre_aggregate, FanOutPolicy};
+use crate::policy::rules::enforce_profile_isolation;
 use crate::schema::registry::SchemaRegistry;
 
-use crate::dsl::plan::{PlanFilter};
-use crate::dsl::report_spec::{Filter, FilterOp};
+use crate::dsl::plan::{PlanFilter, SqlParam, FIRST_FILTER_PARAM};
+use crate::schema::field_catalog::FieldType;
+use crate::dsl::report_spec::{ExistsFilter, Filter, FilterExpr, FilterOp, NamedFilter};
+use crate::sql::expr::{function_args, rewrite_sql};
+use sqlparser::ast::{self, Expr};
 use anyhow::{anyhow, Result};
 use serde_json::Value;
 
 use crate::dsl::plan::{PlanProjection};
-use crate::dsl::report_spec::SelectItem;
+use crate::dsl::plan::{PlanGroupBy, PlanRank};
+use crate::dsl::report_spec::{Aggregate, DateTrunc, HavingFilter, RankSpec, SelectItem};
+use crate::dsl::dates::{format_timestamp, Clock, DateBound, RelativeDate, SystemClock};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
 
 
 use crate::dsl::plan::{PlanOrder, SortDirection};
 use crate::dsl::report_spec::{OrderBy, SortDir};
 
+/// Caller-supplied inputs the ReportSpec itself must not control.
+#[derive(Debug, Clone)]
+pub struct CompileContext {
+    /// Profiles the caller may read; the plan is isolated to them on its root table.
+    pub profiles: Vec<String>,
+    /// "Now" for relative date filters; the system clock unless a test pins it.
+    pub clock: Arc<dyn Clock>,
+    /// What to do when a join would duplicate the report's rows.
+    pub fan_out: FanOutPolicy,
+}
+
+impl CompileContext {
+    pub fn for_profile(profile: impl Into<String>) -> Self {
+        Self::for_profiles([profile.into()])
+    }
+
+    pub fn for_profiles<I, S>(profiles: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            profiles: profiles.into_iter().map(Into::into).collect(),
+            clock: Arc::new(SystemClock),
+            fan_out: FanOutPolicy::default(),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    pub fn with_fan_out(mut self, policy: FanOutPolicy) -> Self {
+        self.fan_out = policy;
+        self
+    }
+}
+
 #[derive(Debug)]
 pub enum CompileError {
     InvalidLimit { value: i64 },
     InvalidOffset { value: i64 },
+    LimitExceedsMax { value: u64, max: u64 },
+    MissingProfile,
 }
 
 impl fmt::Display for CompileError {
@@ -28,13 +84,36 @@ impl fmt::Display for CompileError {
         match self {
             CompileError::InvalidLimit { value } => write!(f, "Invalid limit: {}", value),
             CompileError::InvalidOffset { value } => write!(f, "Invalid offset: {}", value),
+            CompileError::LimitExceedsMax { value, max } => {
+                write!(f, "Limit {} exceeds the workspace maximum of {} rows", value, max)
+            }
+            CompileError::MissingProfile => write!(f, "Compile context must name at least one profile"),
         }
     }
 }
 
 impl std::error::Error for CompileError {}
 
-fn compile_pagination(spec: &ReportSpec) -> Result<(Option<u64>, Option<u64>), CompileError> {
+impl From<&CompileError> for Diagnostic {
+    fn from(e: &CompileError) -> Self {
+        let (code, pointer) = match e {
+            CompileError::InvalidLimit { .. } => ("invalid_limit", "/pagination/limit"),
+            CompileError::InvalidOffset { .. } => ("invalid_offset", "/pagination/offset"),
+            CompileError::LimitExceedsMax { .. } => ("limit_exceeds_max", "/pagination/limit"),
+            CompileError::MissingProfile => ("missing_profile", ""),
+        };
+        let suggestion = match e {
+            CompileError::LimitExceedsMax { max, .. } => Some(format!("page through the rows with limit <= {} and offset", max)),
+            _ => None,
+        };
+        Diagnostic::error(code, e.to_string(), pointer).with_suggestion(suggestion)
+    }
+}
+
+/// Resolve LIMIT/OFFSET for the plan.
+/// - preview: falls back to `preview_default` and is clamped to `preview_max`
+/// - export: unbounded unless asked, but a limit above `export_max` is rejected
+fn compile_pagination(spec: &ReportSpec, limits: &RowLimits) -> Result<(Option<u64>, Option<u64>), CompileError> {
     let limit = spec.pagination.as_ref().and_then(|p| p.limit);
     let offset = spec.pagination.as_ref().and_then(|p| p.offset);
 
@@ -52,6 +131,16 @@ fn compile_pagination(spec: &ReportSpec) -> Result<(Option<u64>, Option<u64>), C
         Some(v) => return Err(CompileError::InvalidOffset { value: v }),
     };
 
+    let limit_u = match spec.mode {
+        Mode::Preview => Some(limit_u.unwrap_or(limits.preview_default).min(limits.preview_max)),
+        Mode::Export => match limit_u {
+            Some(v) if v > limits.export_max => {
+                return Err(CompileError::LimitExceedsMax { value: v, max: limits.export_max })
+            }
+            other => other,
+        },
+    };
+
     Ok((limit_u, offset_u))
 }
 
@@ -84,50 +173,234 @@ fn normalize_join_condition_for_aliases(
     }
 }
 
-/// Translate a single field name into its SQL expression, reusing the same logic as in projections.
-/// Uses the alias_map to prefix columns and derives JSON paths where needed.
-fn field_to_sql_expr(field: &str, alias_map: &HashMap<String, String>) -> Option<String> {
-    Some(match field {
-        // Direct fields on known entities
-        "partnership_id" => format!("{}.id", alias_map.get("partners")?),
-        "campaign_id" => format!("{}.id", alias_map.get("campaigns_latest")?),
-        "campaign_name" => format!("{}.name", alias_map.get("campaigns_latest")?),
-        "offer_id" => format!("{}.id", alias_map.get("offers_latest")?),
-        "offer_name" => format!("{}.name", alias_map.get("offers_latest")?),
-        "workflow_status" => format!("{}.status", alias_map.get("offers_latest")?),
-        "countries" => format!("{}.countries", alias_map.get("offers_latest")?),
-        "package_id" => format!("{}.attributes ->> 'packageId'", alias_map.get("offers_latest")?),
-        other => other.to_string(), // fallback to raw field name
+/// Render a JSON path such as `$.packageId` (or `$.a.b`) inside `alias.column`.
+///
+/// Text values use `->>`/`#>>`; numbers, booleans and dates cast that text, and string
+/// arrays unnest the JSON array (`->`/`#>`) into a text[] so `&&`/`@>` filters work.
+/// Columns not typed `jsonb` (e.g. `json` or `text`) are cast to `jsonb` first. Keys are
+/// quoted as SQL literals, so a `'` in a key cannot end the string early.
+fn json_path_expr(alias: &str, column: &str, jsonb: bool, path: &str, field_type: FieldType) -> Result<String> {
+    let keys = path
+        .strip_prefix("$.")
+        .ok_or_else(|| anyhow!("unsupported JSON path '{}' on {}", path, column))?
+        .split('.')
+        .map(|key| key.replace('\'', "''"))
+        .collect::<Vec<_>>();
+    let document = if jsonb {
+        format!("{}.{}", alias, column)
+    } else {
+        format!("{}.{}::jsonb", alias, column)
+    };
+    let (json, text) = match keys.as_slice() {
+        [key] => (format!("{} -> '{}'", document, key), format!("{} ->> '{}'", document, key)),
+        _ => {
+            let keys = keys.join(",");
+            (format!("{} #> '{{{}}}'", document, keys), format!("{} #>> '{{{}}}'", document, keys))
+        }
+    };
+
+    Ok(match field_type {
+        FieldType::String | FieldType::Enum => text,
+        FieldType::Number => format!("({})::numeric", text),
+        FieldType::Bool => format!("({})::boolean", text),
+        FieldType::Date => format!("({})::timestamptz", text),
+        FieldType::StringArray => format!("ARRAY(SELECT jsonb_array_elements_text({}))", json),
     })
 }
 
+/// Render a derived field as SQL over table aliases.
+///
+/// The SQL is parsed and only these nodes are replaced, keeping the rest as the card wrote
+/// it; entity names that prefix one another (`offers_latest`, `offer_products`) or appear
+/// in string literals are left alone:
+/// - `entity.column` becomes `alias.column`
+/// - `:param` slots become their SQL in `values`
+/// - each derived field it reads is expanded in place, parenthesised, with the arguments
+///   of its call; dependencies are expanded before the fields that use them
+///
+/// `stack` holds the fields being expanded, so a cycle is an error instead of endless
+/// recursion.
+fn expand_derived(
+    df: &DerivedField,
+    values: &HashMap<String, String>,
+    alias_map: &HashMap<String, String>,
+    cards: &SchemaCards,
+    stack: &mut Vec<String>,
+) -> Result<String> {
+    if let Some(i) = stack.iter().position(|n| *n == df.name) {
+        return Err(anyhow!("derived fields form a cycle: {} -> {}", stack[i..].join(" -> "), df.name));
+    }
+    df.parse()?;
+
+    let value = |slot: &str| {
+        values
+            .get(slot)
+            .cloned()
+            .ok_or_else(|| anyhow!("derived field {} has no value for :{}", df.name, slot))
+    };
+    stack.push(df.name.clone());
+    let sql = rewrite_sql(&df.sql, &mut |e| {
+        if let Some(slot) = DerivedField::placeholder(e) {
+            return value(slot).map(Some);
+        }
+        let (name, args) = match e {
+            Expr::CompoundIdentifier(parts) => {
+                let alias = alias_map
+                    .get(&parts[0].value)
+                    .ok_or_else(|| anyhow!("missing alias for {} when rendering {}", parts[0].value, df.name))?;
+                return Ok(Some(format!("{}.{}", alias, parts[1])));
+            }
+            Expr::Identifier(name) => (name.value.clone(), vec![]),
+            Expr::Function(f) if df.derived_dependencies().any(|d| d == f.name.to_string()) => {
+                (f.name.to_string(), function_args(f)?)
+            }
+            _ => return Ok(None),
+        };
+        let dep = cards
+            .derived_field(&name)
+            .ok_or_else(|| anyhow!("derived field {} reads unknown derived field {}", df.name, name))?;
+        // Arguments are literals of the card, or the caller's own params.
+        let given = dep
+            .params
+            .iter()
+            .zip(args)
+            .map(|(p, arg)| {
+                let sql = DerivedField::placeholder(arg).map_or_else(|| Ok(arg.to_string()), value)?;
+                Ok((p.name.clone(), sql))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+        let values = with_defaults(dep, given)?;
+        Ok(Some(format!("({})", expand_derived(dep, &values, alias_map, cards, stack)?)))
+    })?;
+    stack.pop();
+    Ok(sql)
+}
+
+/// The SQL of each of `df`'s params: the value `given` for it, else its default.
+fn with_defaults(df: &DerivedField, mut given: HashMap<String, String>) -> Result<HashMap<String, String>> {
+    for p in &df.params {
+        if given.contains_key(&p.name) {
+            continue;
+        }
+        let default = p
+            .default
+            .as_ref()
+            .and_then(|d| sql_literal(d, p.param_type))
+            .ok_or_else(|| anyhow!("derived field {} needs a value for {}; pass it in a filter's args", df.name, p.name))?;
+        given.insert(p.name.clone(), default);
+    }
+    Ok(given)
+}
+
+/// A derived param default as a SQL literal. Defaults come from the schema cards, not the
+/// ReportSpec, so they are written into the SQL like the rest of the card.
+fn sql_literal(v: &Value, ty: FieldType) -> Option<String> {
+    match (ty, v) {
+        (FieldType::StringArray, Value::Array(items)) => {
+            let items = items
+                .iter()
+                .map(|item| sql_literal(item, FieldType::String))
+                .collect::<Option<Vec<_>>>()?;
+            Some(format!("ARRAY[{}]", items.join(", ")))
+        }
+        (FieldType::String | FieldType::Enum | FieldType::Date, Value::String(s)) => {
+            Some(format!("'{}'", s.replace('\'', "''")))
+        }
+        (FieldType::Number, Value::Number(n)) => Some(n.to_string()),
+        (FieldType::Bool, Value::Bool(b)) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Bind a filter's `args` for the params of derived field `df` and return the placeholder
+/// of each; params without an argument use their default.
+fn bind_derived_args(
+    df: &DerivedField,
+    args: &BTreeMap<String, Value>,
+    params: &mut Vec<SqlParam>,
+) -> Result<HashMap<String, String>> {
+    if let Some(unknown) = args.keys().find(|a| !df.params.iter().any(|p| &p.name == *a)) {
+        return Err(anyhow!("derived field {} has no param {}", df.name, unknown));
+    }
+    let given = df
+        .params
+        .iter()
+        .filter_map(|p| args.get(&p.name).map(|v| (p, v)))
+        .map(|(p, v)| {
+            let placeholder = bind_typed(params, v, p.param_type)
+                .ok_or_else(|| anyhow!("argument {} of derived field {} must be {}", p.name, df.name, p.param_type.expected()))?;
+            Ok((p.name.clone(), placeholder))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+    with_defaults(df, given)
+}
+
+/// Translate a single field name into its SQL expression using the workspace field catalog.
+/// Shared by projections, filters and ordering so a field renders the same everywhere.
+fn field_to_sql_expr(field: &str, alias_map: &HashMap<String, String>, cards: &SchemaCards) -> Result<String> {
+    let card = cards
+        .field(field)
+        .ok_or_else(|| anyhow!("field {} is not in the workspace field catalog", field))?;
+    let node = card.role.as_ref().unwrap_or(&card.entity);
+    let alias = alias_map
+        .get(node)
+        .ok_or_else(|| anyhow!("missing alias for {} when rendering {}", node, field))?;
+
+    match (&card.column, &card.json_path) {
+        (Some(column), None) => Ok(format!("{}.{}", alias, column)),
+        (Some(column), Some(path)) => {
+            // Without a column card, trust the path and read the column as jsonb.
+            let jsonb = cards
+                .column(&card.entity, column)
+                .is_none_or(|c| c.data_type == "jsonb");
+            json_path_expr(alias, column, jsonb, path, card.field_type)
+        }
+        (None, _) => cards
+            .derived_field(field)
+            .ok_or_else(|| anyhow!("field {} has neither a column nor a derived field definition", field))
+            .and_then(|df| expand_derived(df, &with_defaults(df, HashMap::new())?, alias_map, cards, &mut Vec::new())),
+    }
+}
+
+/// A field as the operand of a filter operator. Derived SQL is parenthesised so the
+/// operator applies to all of it, e.g. `(a BETWEEN 0 AND $2) = $3`; `args` are bound for
+/// its params.
+fn filter_operand(
+    field: &str,
+    args: &BTreeMap<String, Value>,
+    alias_map: &HashMap<String, String>,
+    cards: &SchemaCards,
+    params: &mut Vec<SqlParam>,
+) -> Result<String> {
+    match cards.field(field) {
+        Some(card) if card.column.is_none() => {
+            let df = cards
+                .derived_field(field)
+                .ok_or_else(|| anyhow!("field {} has neither a column nor a derived field definition", field))?;
+            let values = bind_derived_args(df, args, params)?;
+            Ok(format!("({})", expand_derived(df, &values, alias_map, cards, &mut Vec::new())?))
+        }
+        _ if !args.is_empty() => Err(anyhow!("field {} takes no args", field)),
+        _ => field_to_sql_expr(field, alias_map, cards),
+    }
+}
+
 /// Translate the order_by specifications into PlanOrder entries.
 ///
-/// It uses the same field-to-expression mapping as in projections, then sets
+/// It uses the same field-to-expression mapping as in projections (including a
+/// select item's date_trunc bucket), then sets
 /// SortDirection based on the `dir` (asc/desc). Returns an error if a field
 /// cannot be mapped or an alias is missing.
 pub fn translate_ordering(
     order_by: &[OrderBy],
+    select: &[SelectItem],
     alias_map: &HashMap<String, String>,
     cards: &SchemaCards,
 ) -> Result<Vec<PlanOrder>> {
     order_by
         .iter()
         .map(|item| {
-            // Determine the SQL expression for ordering. Derived fields are handled
-            // via replacement on the derived SQL (as in projections).
-            let expr = if let Some(df) = cards.derived_fields.iter().find(|df| df.name == item.field) {
-                // Replace table names in the derived SQL with aliases
-                alias_map
-                    .iter()
-                    .fold(df.sql.clone(), |acc, (entity, alias)| {
-                        acc.replace(&format!("{}.", entity), &format!("{}.", alias))
-                    })
-            } else {
-                // For direct fields, map to alias.column or fallback via field_to_sql_expr
-                field_to_sql_expr(&item.field, alias_map)
-                    .ok_or_else(|| anyhow!("cannot map order_by field {}", item.field))?
-            };
+            let expr = grouping_sql_expr(&item.field, select, alias_map, cards)?;
 
             // Map direction to SortDirection
             let direction = match item.dir {
@@ -144,10 +417,54 @@ pub fn translate_ordering(
 }
 
 
+/// Truncate a date expression to the start of its bucket.
+fn date_trunc_sql_expr(trunc: DateTrunc, expr: &str) -> String {
+    let unit = match trunc {
+        DateTrunc::Day => "day",
+        DateTrunc::Week => "week",
+        DateTrunc::Month => "month",
+        DateTrunc::Quarter => "quarter",
+        DateTrunc::Year => "year",
+    };
+    format!("DATE_TRUNC('{}', {})", unit, expr)
+}
+
+/// The expression a field is grouped and ordered by: its date_trunc bucket if an
+/// unaggregated select item buckets it, else the plain field expression.
+fn grouping_sql_expr(
+    field: &str,
+    select: &[SelectItem],
+    alias_map: &HashMap<String, String>,
+    cards: &SchemaCards,
+) -> Result<String> {
+    let expr = field_to_sql_expr(field, alias_map, cards)?;
+    let trunc = select
+        .iter()
+        .find(|s| s.field == field && s.agg.is_none() && s.date_trunc.is_some())
+        .and_then(|s| s.date_trunc);
+
+    Ok(trunc.map_or(expr.clone(), |t| date_trunc_sql_expr(t, &expr)))
+}
+
+/// Wrap a field expression in its aggregate function.
+fn aggregate_sql_expr(agg: Aggregate, expr: &str) -> String {
+    match agg {
+        Aggregate::Count => format!("COUNT({})", expr),
+        Aggregate::CountDistinct => format!("COUNT(DISTINCT {})", expr),
+        Aggregate::Sum => format!("SUM({})", expr),
+        Aggregate::Min => format!("MIN({})", expr),
+        Aggregate::Max => format!("MAX({})", expr),
+        Aggregate::Avg => format!("AVG({})", expr),
+        // Joins fan rows out, so de-duplicate; ordering keeps the output deterministic.
+        Aggregate::StringAgg => format!("STRING_AGG(DISTINCT {0}, ',' ORDER BY {0})", expr),
+    }
+}
+
 /// Translate the select list into SQL projections.
 /// Each entry becomes a PlanProjection containing:
 ///   - field: the original report field name
-///   - expression: the SQL expression with table aliases
+///   - expression: the SQL expression with table aliases, wrapped in its date_trunc
+///     bucket or aggregate if any
 ///   - alias: an optional alias provided in the ReportSpec
 pub fn translate_projections(
     select: &[SelectItem],
@@ -157,234 +474,543 @@ pub fn translate_projections(
     select
         .iter()
         .map(|item| {
-            // Determine the SQL expression for this field.
-            let expr = match item.field.as_str() {
-                // Direct fields that map to simple column names
-                "partnership_id" => {
-                    format!("{}.id", alias_map.get("partners").ok_or_else(|| {
-                        anyhow!("missing alias for partners when rendering partnership_id")
-                    })?)
-                }
-                "campaign_id" => {
-                    format!("{}.id", alias_map.get("campaigns_latest").ok_or_else(|| {
-                        anyhow!("missing alias for campaigns_latest when rendering campaign_id")
-                    })?)
-                }
-                "campaign_name" => {
-                    format!("{}.name", alias_map.get("campaigns_latest").ok_or_else(|| {
-                        anyhow!("missing alias for campaigns_latest when rendering campaign_name")
-                    })?)
-                }
-                "offer_id" => {
-                    format!("{}.id", alias_map.get("offers_latest").ok_or_else(|| {
-                        anyhow!("missing alias for offers_latest when rendering offer_id")
-                    })?)
-                }
-                "offer_name" => {
-                    format!("{}.name", alias_map.get("offers_latest").ok_or_else(|| {
-                        anyhow!("missing alias for offers_latest when rendering offer_name")
-                    })?)
-                }
-                "workflow_status" => {
-                    // workflow_status is stored in offers_latest.status
-                    format!("{}.status", alias_map.get("offers_latest").ok_or_else(|| {
-                        anyhow!("missing alias for offers_latest when rendering workflow_status")
-                    })?)
-                }
-                "countries" => {
-                    format!("{}.countries", alias_map.get("offers_latest").ok_or_else(|| {
-                        anyhow!("missing alias for offers_latest when rendering countries")
-                    })?)
-                }
-                // Derived or special-case fields
-                "package_id" => {
-                    // Map to the JSON path attributes->>'packageId' on offers_latest
-                    format!(
-                        "{}.attributes ->> 'packageId'",
-                        alias_map.get("offers_latest").ok_or_else(|| {
-                            anyhow!("missing alias for offers_latest when rendering package_id")
-                        })?
-                    )
-                }
-                // Fields defined in derived_fields (expired_or_live_status, products_csv, etc.)
-                other => {
-                    if let Some(df) = cards.derived_fields.iter().find(|df| df.name == other) {
-                        // Replace table names in the derived SQL with aliases
-                        alias_map
-                            .iter()
-                            .fold(df.sql.clone(), |acc, (entity, alias)| {
-                                acc.replace(&format!("{}.", entity), &format!("{}.", alias))
-                            })
-                    } else {
-                        // Fallback: direct column with field name (for unknown but valid columns)
-                        // Attempt to resolve via resolve_entity and then prefix alias
-                        let entity = resolve_entity(other, cards).ok_or_else(|| {
-                            anyhow!("cannot find entity for projection field {}", other)
-                        })?;
-                        let alias = alias_map.get(entity).ok_or_else(|| {
-                            anyhow!("missing alias for {} when rendering {}", entity, other)
-                        })?;
-                        format!("{}.{}", alias, other)
-                    }
-                }
-            };
+            let expr = field_to_sql_expr(&item.field, alias_map, cards)?;
+            let expr = item.date_trunc.map_or(expr.clone(), |t| date_trunc_sql_expr(t, &expr));
 
             Ok(PlanProjection {
                 field: item.field.clone(),
-                expression: expr,
+                expression: item.agg.map_or(expr.clone(), |agg| aggregate_sql_expr(agg, &expr)),
                 alias: item.alias.clone(),
             })
         })
         .collect()
 }
 
+fn sort_direction(dir: &SortDir) -> SortDirection {
+    match dir {
+        SortDir::Asc => SortDirection::Asc,
+        SortDir::Desc => SortDirection::Desc,
+    }
+}
 
+/// The column a select item is read back as from the `ranked` CTE.
+fn output_name(item: &SelectItem) -> String {
+    item.alias.clone().unwrap_or_else(|| item.field.clone())
+}
 
+/// Translate a rank spec into a PlanRank. Partition and unaggregated order keys use the
+/// same grouping expressions as GROUP BY, so they stay valid on grouped reports.
+///
+/// `tiebreaker` ends the window's ORDER BY so ROW_NUMBER is deterministic when the rank's
+/// own keys tie; see `rank_tiebreaker`.
+pub fn translate_rank(
+    rank: &RankSpec,
+    select: &[SelectItem],
+    projections: &[PlanProjection],
+    tiebreaker: &[String],
+    alias_map: &HashMap<String, String>,
+    cards: &SchemaCards,
+) -> Result<PlanRank> {
+    let partition_by = rank
+        .partition_by
+        .iter()
+        .map(|field| grouping_sql_expr(field, select, alias_map, cards))
+        .collect::<Result<Vec<_>>>()?;
 
-/// Translate a single filter into SQL.
-/// Returns None if the filter cannot be expressed.
-fn translate_filter(filter: &Filter, alias_map: &HashMap<String, String>) -> Option<String> {
-    // First determine the SQL expression for the field.
-    let column_sql = field_to_sql_expr(&filter.field, alias_map)?;
-
-    match filter.op {
-        FilterOp::Eq => {
-            // Expect scalar values; wrap strings in single quotes.
-            let rhs = match &filter.value {
-                Value::String(s) => format!("'{}'", s.replace('\'', "''")),
-                Value::Bool(b) => b.to_string(),
-                Value::Number(n) => n.to_string(),
-                _ => return None,
+    let mut order_by = rank
+        .order_by
+        .iter()
+        .map(|ro| {
+            let expr = match ro.agg {
+                Some(agg) => aggregate_sql_expr(agg, &field_to_sql_expr(&ro.field, alias_map, cards)?),
+                None => grouping_sql_expr(&ro.field, select, alias_map, cards)?,
             };
-            Some(format!("{} = {}", column_sql, rhs))
+            Ok(PlanOrder { expression: expr, direction: sort_direction(&ro.dir) })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for expr in tiebreaker {
+        if !order_by.iter().any(|o| &o.expression == expr) {
+            order_by.push(PlanOrder { expression: expr.clone(), direction: SortDirection::Asc });
         }
-        FilterOp::In => {
-            // Expect array of scalars; wrap string elements in quotes.
-            let arr = match &filter.value {
-                Value::Array(vals) if !vals.is_empty() => vals,
-                _ => return None,
-            };
-            let vals_sql = arr
-                .iter()
-                .filter_map(|v| {
-                    Some(match v {
-                        Value::String(s) => format!("'{}'", s.replace('\'', "''")),
-                        Value::Bool(b) => b.to_string(),
-                        Value::Number(n) => n.to_string(),
-                        _ => return None?,
-                    })
-                })
-                .collect::<Vec<_>>()
-                .join(", ");
-            Some(format!("{} IN ({})", column_sql, vals_sql))
+    }
+
+    // Columns the outer query sorts on but the select list does not output.
+    let mut keys: Vec<PlanProjection> = Vec::new();
+    for expr in partition_by.iter().chain(tiebreaker) {
+        let selected = projections.iter().any(|p| p.alias.is_some() && &p.expression == expr);
+        if !selected && !keys.iter().any(|k| &k.expression == expr) {
+            keys.push(PlanProjection {
+                field: String::new(),
+                expression: expr.clone(),
+                alias: Some(format!("{}_key_{}", rank.alias, keys.len() + 1)),
+            });
         }
-        FilterOp::Overlaps => {
-            // For array overlap queries (e.g. countries).
-            let arr = match &filter.value {
-                Value::Array(vals) if !vals.is_empty() => vals,
-                _ => return None,
-            };
-            let elements_sql = arr
+    }
+
+    Ok(PlanRank {
+        partition_by,
+        order_by,
+        alias: rank.alias.clone(),
+        top_n: rank.top_n,
+        keys,
+    })
+}
+
+/// What breaks ties between rows the rank's own keys consider equal: the group keys of an
+/// aggregated report, otherwise the root entity's primary key.
+fn rank_tiebreaker(group_by: &[PlanGroupBy], root: &PlanTable, cards: &SchemaCards) -> Result<Vec<String>> {
+    if !group_by.is_empty() {
+        return Ok(group_by.iter().map(|g| g.expression.clone()).collect());
+    }
+    let entity = cards
+        .entities
+        .iter()
+        .find(|e| e.name == root.name)
+        .ok_or_else(|| anyhow!("unknown root entity {}", root.name))?;
+    Ok(entity.primary_key.iter().map(|c| format!("{}.{}", root.alias, c)).collect())
+}
+
+/// Ordering of the ranked output, on the `ranked` CTE's column names: the report's
+/// order_by, then the partition keys so each partition's rows stay together, then the rank,
+/// then the tiebreaker.
+fn translate_ranked_ordering(
+    order_by: &[OrderBy],
+    select: &[SelectItem],
+    projections: &[PlanProjection],
+    rank: &PlanRank,
+    tiebreaker: &[String],
+) -> Result<Vec<PlanOrder>> {
+    let column = |expr: &String| {
+        projections
+            .iter()
+            .chain(&rank.keys)
+            .find(|p| &p.expression == expr)
+            .and_then(|p| p.alias.clone())
+            .ok_or_else(|| anyhow!("rank key {} is not a column of the ranked query", expr))
+    };
+    let asc = |expression: String| PlanOrder { expression, direction: SortDirection::Asc };
+
+    let mut ordering = order_by
+        .iter()
+        .map(|ob| {
+            let item = select
                 .iter()
-                .filter_map(|v| match v {
-                    Value::String(s) => Some(format!("'{}'", s.replace('\'', "''"))),
-                    _ => None,
-                })
-                .collect::<Vec<_>>()
-                .join(", ");
-            Some(format!("{} && ARRAY[{}]", column_sql, elements_sql))
-        }
-        FilterOp::Gte | FilterOp::Lte => {
-            // Greater-than or less-than comparisons (dates or numbers)
-            let op_str = if matches!(filter.op, FilterOp::Gte) { ">=" } else { "<=" };
-            let rhs = match &filter.value {
-                Value::String(s) => format!("'{}'", s.replace('\'', "''")),
-                Value::Number(n) => n.to_string(),
-                _ => return None,
-            };
-            Some(format!("{} {} {}", column_sql, op_str, rhs))
+                .find(|s| s.field == ob.field)
+                .ok_or_else(|| anyhow!("order_by field {} is not selected", ob.field))?;
+            Ok(PlanOrder { expression: output_name(item), direction: sort_direction(&ob.dir) })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let partition = rank.partition_by.iter().map(column).collect::<Result<Vec<_>>>()?;
+    let ties = tiebreaker.iter().map(column).collect::<Result<Vec<_>>>()?;
+    for name in partition.into_iter().chain([rank.alias.clone()]).chain(ties) {
+        if !ordering.iter().any(|o| o.expression == name) {
+            ordering.push(asc(name));
         }
     }
+    Ok(ordering)
 }
 
-/// Translate all filters of a report spec into a Vec<PlanFilter> (functional style).
-pub fn translate_filters(
-    filters: &[Filter],
+/// Translate having entries into PlanFilters on the aggregate expressions, binding their
+/// values into `params` after the WHERE clause parameters.
+pub fn translate_having(
+    having: &[HavingFilter],
     alias_map: &HashMap<String, String>,
-    _cards: &SchemaCards,
+    cards: &SchemaCards,
+    now: DateTime<Utc>,
+    params: &mut Vec<SqlParam>,
 ) -> Result<Vec<PlanFilter>> {
-    // The `cards` parameter is included for future extensions (e.g. dynamic derived field discovery),
-    // but not used in this minimal example.
-    filters
+    having
         .iter()
-        .map(|f| {
-            translate_filter(f, alias_map)
+        .map(|h| {
+            let field_type = cards.field(&h.field).map_or(FieldType::String, |c| c.field_type);
+            let (expr, ty) = match h.agg {
+                Some(agg) => (
+                    aggregate_sql_expr(agg, &field_to_sql_expr(&h.field, alias_map, cards)?),
+                    agg.result_type(field_type),
+                ),
+                // an aggregate derived field, e.g. products_csv
+                None => (filter_operand(&h.field, &BTreeMap::new(), alias_map, cards, params)?, field_type),
+            };
+            let filter = Filter { field: h.field.clone(), op: h.op, value: h.value.clone(), args: BTreeMap::new() };
+
+            translate_filter(&filter, &expr, ty, now, params)
                 .map(|sql| PlanFilter { expression: sql })
-                .ok_or_else(|| anyhow!("invalid filter: {:?}", f))
+                .ok_or_else(|| anyhow!("invalid having: {:?}", h))
         })
         .collect()
 }
 
+/// Translate grouping into PlanGroupBy entries.
+///
+/// An explicit `group_by` is used as given. Otherwise a report that selects any aggregate
+/// is grouped by its non-aggregated select fields, in select order; anything else is not
+/// grouped at all.
+pub fn translate_group_by(
+    spec: &ReportSpec,
+    alias_map: &HashMap<String, String>,
+    cards: &SchemaCards,
+) -> Result<Vec<PlanGroupBy>> {
+    let is_plain = |item: &&SelectItem| item.agg.is_none() && !cards.is_aggregate_field(&item.field);
+
+    let fields: Vec<&str> = if !spec.group_by.is_empty() {
+        spec.group_by.iter().map(String::as_str).collect()
+    } else if spec.select.iter().all(|item| is_plain(&item)) {
+        vec![]
+    } else {
+        spec.select.iter().filter(is_plain).map(|item| item.field.as_str()).collect()
+    };
 
-fn resolve_entity<'a>(field: &str, cards: &'a SchemaCards) -> Option<&'a str> {
-    // 1. Hard-coded mapping for the campaigns_offers workspace
-    match field {
-        // partner-level field
-        "partnership_id" => return Some("partners"),
-        // campaign-level fields
-        "campaign_id" | "campaign_name" => return Some("campaigns_latest"),
-        // offer-level fields (direct columns)
-        "offer_id" | "offer_name" | "workflow_status" | "countries" | "package_id" => {
-            return Some("offers_latest")
+    fields
+        .iter()
+        .enumerate()
+        .filter(|(i, field)| !fields[..*i].contains(field))
+        .map(|(_, field)| {
+            Ok(PlanGroupBy {
+                field: field.to_string(),
+                expression: grouping_sql_expr(field, &spec.select, alias_map, cards)?,
+            })
+        })
+        .collect()
+}
+
+/// Convert a scalar JSON value into a typed bind parameter for a field of type `ty`.
+fn sql_param(v: &Value, ty: FieldType) -> Option<SqlParam> {
+    match v {
+        Value::String(s) if ty == FieldType::Date => Some(SqlParam::Date(s.clone())),
+        Value::String(s) => Some(SqlParam::Text(s.clone())),
+        Value::Bool(b) => Some(SqlParam::Bool(*b)),
+        Value::Number(n) => n.as_i64().map(SqlParam::Int).or_else(|| n.as_f64().map(SqlParam::Float)),
+        _ => None,
+    }
+}
+
+/// Push a bind parameter and return its `$n` placeholder.
+fn bind(params: &mut Vec<SqlParam>, param: SqlParam) -> String {
+    params.push(param);
+    format!("${}", FIRST_FILTER_PARAM + params.len() - 1)
+}
+
+/// Bind a scalar JSON value and return its placeholder.
+fn bind_value(params: &mut Vec<SqlParam>, v: &Value, ty: FieldType) -> Option<String> {
+    let param = sql_param(v, ty)?;
+    Some(bind(params, param))
+}
+
+/// Bind a value of a declared type: a scalar, or a string array as `ARRAY[$2, $3]`.
+fn bind_typed(params: &mut Vec<SqlParam>, v: &Value, ty: FieldType) -> Option<String> {
+    match ty {
+        FieldType::StringArray => bind_list(params, v, FieldType::String).map(|p| format!("ARRAY[{}]", p)),
+        ty => bind_value(params, v, ty),
+    }
+}
+
+/// Bind each element of a non-empty array and return the comma-separated placeholders.
+fn bind_list(params: &mut Vec<SqlParam>, v: &Value, ty: FieldType) -> Option<String> {
+    match v {
+        Value::Array(vals) if !vals.is_empty() => {
+            let placeholders = vals
+                .iter()
+                .map(|e| bind_value(params, e, ty))
+                .collect::<Option<Vec<_>>>()?;
+            Some(placeholders.join(", "))
+        }
+        _ => None,
+    }
+}
+
+/// Escape LIKE wildcards so user text is matched literally (Postgres default escape is `\`).
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// A user's LIKE pattern as Postgres reads it: `%` and `_` stay wildcards, everything else
+/// is matched literally. `\` makes the next character literal (`50\%` is "50%"), and a
+/// trailing `\` is a literal backslash rather than an error.
+fn like_pattern(s: &str) -> String {
+    let mut pattern = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let literal = match c {
+            '%' | '_' => {
+                pattern.push(c);
+                continue;
+            }
+            '\\' => chars.next().unwrap_or('\\'),
+            c => c,
+        };
+        if matches!(literal, '\\' | '%' | '_') {
+            pattern.push('\\');
         }
-        // derived fields that live on offers_latest
-        "expired_or_live_status" => return Some("offers_latest"),
-        // derived aggregation that comes from offer_products
-        "products_csv" => return Some("offer_products"),
-        // filter-only field that comes from offer_phases
-        "promo_type" => return Some("offer_phases"),
-        _ => { /* fall through to dynamic lookup */ }
-    }
-
-    // 2. Dynamic lookup for other cases
-    // 2a. If this is a derived field defined in schema_cards, inspect its dependencies.
-    if let Some(derived) = cards.derived_fields.iter().find(|df| df.name == field) {
-        // e.g. "offers_latest.end_date" ⇒ entity is "offers_latest"
-        if let Some(dep) = derived.depends_on.first() {
-            if let Some((entity, _)) = dep.split_once('.') {
-                return Some(entity);
+        pattern.push(literal);
+    }
+    pattern
+}
+
+/// Translate a single filter on `column_sql` into SQL, binding its values into `params`.
+/// Relative dates are resolved against `now`. Returns None if the filter cannot be expressed.
+fn translate_filter(
+    filter: &Filter,
+    column_sql: &str,
+    ty: FieldType,
+    now: DateTime<Utc>,
+    params: &mut Vec<SqlParam>,
+) -> Option<String> {
+    let v = &filter.value;
+    match filter.op {
+        FilterOp::Eq => Some(format!("{} = {}", column_sql, bind_value(params, v, ty)?)),
+        FilterOp::Neq => Some(format!("{} <> {}", column_sql, bind_value(params, v, ty)?)),
+        FilterOp::Gt => Some(format!("{} > {}", column_sql, bind_value(params, v, ty)?)),
+        FilterOp::Gte => Some(format!("{} >= {}", column_sql, bind_value(params, v, ty)?)),
+        FilterOp::Lt => Some(format!("{} < {}", column_sql, bind_value(params, v, ty)?)),
+        FilterOp::Lte => Some(format!("{} <= {}", column_sql, bind_value(params, v, ty)?)),
+        FilterOp::In => Some(format!("{} IN ({})", column_sql, bind_list(params, v, ty)?)),
+        FilterOp::NotIn => Some(format!("{} NOT IN ({})", column_sql, bind_list(params, v, ty)?)),
+        // Array operators (e.g. countries); elements are bound one by one.
+        FilterOp::Overlaps => Some(format!("{} && ARRAY[{}]", column_sql, bind_list(params, v, ty)?)),
+        FilterOp::Contains => Some(format!("{} @> ARRAY[{}]", column_sql, bind_list(params, v, ty)?)),
+        FilterOp::Between => match v.as_array().map(Vec::as_slice) {
+            Some([low, high]) => {
+                let low = bind_value(params, low, ty)?;
+                let high = bind_value(params, high, ty)?;
+                Some(format!("{} BETWEEN {} AND {}", column_sql, low, high))
             }
+            _ => None,
+        },
+        FilterOp::IsNull => Some(format!("{} IS NULL", column_sql)),
+        FilterOp::IsNotNull => Some(format!("{} IS NOT NULL", column_sql)),
+        FilterOp::Relative => match RelativeDate::parse(v.as_str()?)?.resolve(now)? {
+            DateBound::Range { start, end } => {
+                let start = bind(params, SqlParam::Date(start.to_string()));
+                let end = bind(params, SqlParam::Date(end.to_string()));
+                Some(format!("({0} >= {1} AND {0} < {2})", column_sql, start, end))
+            }
+            DateBound::Before(ts) => {
+                Some(format!("{} < {}", column_sql, bind(params, SqlParam::Timestamp(format_timestamp(ts)))))
+            }
+            DateBound::After(ts) => {
+                Some(format!("{} >= {}", column_sql, bind(params, SqlParam::Timestamp(format_timestamp(ts)))))
+            }
+        },
+        FilterOp::StartsWith => {
+            let pattern = format!("{}%", escape_like(v.as_str()?));
+            Some(format!("{} LIKE {}", column_sql, bind(params, SqlParam::Text(pattern))))
+        }
+        FilterOp::ILike => {
+            let pattern = like_pattern(v.as_str()?);
+            Some(format!("{} ILIKE {}", column_sql, bind(params, SqlParam::Text(pattern))))
         }
     }
+}
 
-    // 2b. Otherwise scan all entities to see if the field matches a direct column name.
-    for entity in &cards.entities {
-        if entity.columns.iter().any(|col| col.name == field) {
-            return Some(entity.name.as_str());
+/// What translating a filter needs besides the filter itself: where fields live, "now" for
+/// relative dates, and the soft-delete mode for EXISTS subqueries.
+struct FilterContext<'a> {
+    alias_map: &'a HashMap<String, String>,
+    cards: &'a SchemaCards,
+    now: DateTime<Utc>,
+    deleted: DeletedMode,
+}
+
+/// Translate a filter expression into SQL. Groups are parenthesised so the result can
+/// be ANDed with other predicates as-is.
+fn translate_filter_expr(expr: &FilterExpr, ctx: &FilterContext, params: &mut Vec<SqlParam>) -> Result<String> {
+    let mut group = |children: &[FilterExpr], op: &str| -> Result<String> {
+        let parts = children
+            .iter()
+            .map(|c| translate_filter_expr(c, ctx, params))
+            .collect::<Result<Vec<_>>>()?;
+        match parts.as_slice() {
+            [] => Err(anyhow!("empty filter group")),
+            [single] => Ok(single.clone()),
+            _ => Ok(format!("({})", parts.join(op))),
+        }
+    };
+
+    match expr {
+        FilterExpr::All { all } => group(all, " AND "),
+        FilterExpr::Any { any } => group(any, " OR "),
+        FilterExpr::Not { not } => {
+            // A single-child group renders as its child, so check the SQL, not the variant.
+            let inner = translate_filter_expr(not, ctx, params)?;
+            if is_parenthesized(&inner) {
+                Ok(format!("NOT {}", inner))
+            } else {
+                Ok(format!("NOT ({})", inner))
+            }
+        }
+        FilterExpr::Exists { exists } => translate_exists(exists, ctx, params),
+        FilterExpr::NotExists { not_exists } => Ok(format!("NOT {}", translate_exists(not_exists, ctx, params)?)),
+        FilterExpr::Named(named) => translate_named_filter(named, ctx, params),
+        FilterExpr::Predicate(f) => {
+            let column_sql = filter_operand(&f.field, &f.args, ctx.alias_map, ctx.cards, params)?;
+            let ty = ctx.cards.field(&f.field).map_or(FieldType::String, |c| c.field_type);
+            translate_filter(f, &column_sql, ty, ctx.now, params).ok_or_else(|| anyhow!("invalid filter: {:?}", f))
         }
     }
+}
 
-    // Not found
-    None
+/// Whether `sql` is a single parenthesized term: `(a OR b)`, but not `(a) OR (b)`.
+/// Quoted literals and identifiers are skipped, so a `)` inside one does not count.
+fn is_parenthesized(sql: &str) -> bool {
+    if !sql.starts_with('(') {
+        return false;
+    }
+    let mut depth = 0usize;
+    let mut quote = None;
+    for (i, c) in sql.char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    return i == sql.len() - 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
 }
-fn build_joins(
-    cards: &SchemaCards,
-    required: Vec<&Option<&str>>,
-    alias_map: &HashMap<String, String>,
-) -> Result<Vec<PlanJoin>> {
-    let required_names: Vec<&str> = required
+
+/// Translate an exists filter into a correlated `EXISTS (SELECT 1 ...)` subquery.
+///
+/// The subquery starts at the related entity and follows the shortest safe join path to a
+/// table already in scope; the last edge's ON conditions (profile and version included)
+/// become the correlation. Subquery tables get aliases no enclosing scope uses (`opr1`),
+/// and deleted rows are excluded unless the spec includes them.
+fn translate_exists(exists: &ExistsFilter, ctx: &FilterContext, params: &mut Vec<SqlParam>) -> Result<String> {
+    let in_scope: BTreeSet<String> = ctx.alias_map.keys().filter(|e| **e != exists.entity).cloned().collect();
+    let path = path_to(ctx.cards, &in_scope, &exists.entity)?;
+
+    // Entities along the path, starting at the related entity; the last one is in scope.
+    let nodes = path.iter().fold(vec![exists.entity.clone()], |mut acc, edge| {
+        let last = acc.last().cloned().unwrap_or_default();
+        acc.push(if edge.from == last { edge.to_node().to_string() } else { edge.from.clone() });
+        acc
+    });
+    let inner = &nodes[..nodes.len() - 1];
+    let mut aliases = AliasAllocator::nested(ctx.alias_map.values().cloned());
+    let alias_map: HashMap<String, String> = ctx
+        .alias_map
         .iter()
-        .filter_map(|opt| opt.as_ref())
-        .copied()
+        .map(|(e, a)| (e.clone(), a.clone()))
+        .chain(inner.iter().map(|n| (n.clone(), aliases.allocate(&preferred_alias(ctx.cards, n)))))
         .collect();
+    let entity = |node: &str| ctx.cards.node_entity(node).to_string();
 
-    cards
-        .join_graph
-        .edges
-        .iter()
-        .filter(|edge| {
-            required_names.contains(&edge.from.as_str()) && required_names.contains(&edge.to.as_str())
+    let on = |join: &PlanJoin| {
+        join.conditions
+            .iter()
+            .map(|c| format!("{} = {}", c.left_field, c.right_field))
+            .collect::<Vec<_>>()
+    };
+    let joins = build_joins(&path, &alias_map)?;
+    let (correlation, bridges) = joins.split_last().ok_or_else(|| anyhow!("empty exists path for {}", exists.entity))?;
+
+    let from = std::iter::once(format!("{} {}", entity(&inner[0]), alias_map[&inner[0]]))
+        .chain(bridges.iter().zip(&inner[1..]).map(|(j, n)| {
+            format!("JOIN {} {} ON {}", entity(n), alias_map[n], on(j).join(" AND "))
+        }))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // `only` selects deleted root rows; the subquery's tables are filtered as for `exclude`.
+    let mode = if ctx.deleted == DeletedMode::Include { DeletedMode::Include } else { DeletedMode::Exclude };
+    let inner_ctx = FilterContext { alias_map: &alias_map, ..*ctx };
+    let predicates = on(correlation)
+        .into_iter()
+        .chain(inner.iter().filter_map(|n| soft_delete_predicate(ctx.cards, &entity(n), &alias_map[n], mode)))
+        .map(Ok)
+        .chain(exists.filters.iter().map(|f| translate_filter_expr(f, &inner_ctx, params)))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(format!("EXISTS (SELECT 1 FROM {} WHERE {})", from, predicates.join(" AND ")))
+}
+
+/// The entity card and common filter a named filter refers to.
+fn resolve_named_filter<'a>(named: &'a NamedFilter, cards: &'a SchemaCards) -> Result<(&'a EntityCard, &'a FilterHint)> {
+    let mut found = cards.common_filters(&named.named, named.entity.as_deref());
+    match (found.next(), found.next()) {
+        (Some(hint), None) => Ok(hint),
+        (None, _) => Err(anyhow!("unknown named filter {}", named.named)),
+        (Some(_), Some(_)) => Err(anyhow!("named filter {} is declared on several entities", named.named)),
+    }
+}
+
+/// Expand a named filter from its entity card's common filter: columns are qualified with
+/// the entity's alias and each `:name` slot is bound as its declared type (a `string_array`
+/// element by element, as `ARRAY[$2, $3]`). The rest of the SQL is kept as the card wrote it.
+fn translate_named_filter(named: &NamedFilter, ctx: &FilterContext, params: &mut Vec<SqlParam>) -> Result<String> {
+    let (entity, hint) = resolve_named_filter(named, ctx.cards)?;
+    let alias = ctx
+        .alias_map
+        .get(&entity.name)
+        .ok_or_else(|| anyhow!("missing alias for {} when rendering named filter {}", entity.name, named.named))?;
+    let expr = hint.parse(entity)?;
+
+    let sql = rewrite_sql(&hint.sql, &mut |e| {
+        Ok(match e {
+            Expr::Identifier(column) => Some(format!("{}.{}", alias, column)),
+            Expr::CompoundIdentifier(parts) => Some(format!("{}.{}", alias, parts[1])),
+            Expr::Value(v) => {
+                let ast::Value::Placeholder(p) = &v.value else { return Ok(None) };
+                let slot = p.trim_start_matches(':');
+                let ty = hint
+                    .params
+                    .iter()
+                    .find(|d| d.name == slot)
+                    .map(|d| d.param_type)
+                    .ok_or_else(|| anyhow!("named filter {} has no param {}", named.named, slot))?;
+                let value = named
+                    .params
+                    .get(slot)
+                    .ok_or_else(|| anyhow!("named filter {} is missing param {}", named.named, slot))?;
+                Some(bind_typed(params, value, ty).ok_or_else(|| anyhow!("invalid value {} for param {} of named filter {}", value, slot, named.named))?)
+            }
+            _ => None,
         })
+    })?;
+
+    // Keep an OR (or AND) in the hint together when the filter is combined with others.
+    Ok(match expr {
+        Expr::BinaryOp { op: ast::BinaryOperator::Or | ast::BinaryOperator::And, .. } => format!("({})", sql),
+        _ => sql,
+    })
+}
+
+/// Translate all filters of a report spec into PlanFilters with `$n` placeholders, plus the
+/// bind values for them in placeholder order (starting at FIRST_FILTER_PARAM).
+pub fn translate_filters(
+    filters: &[FilterExpr],
+    alias_map: &HashMap<String, String>,
+    cards: &SchemaCards,
+    now: DateTime<Utc>,
+    deleted: DeletedMode,
+) -> Result<(Vec<PlanFilter>, Vec<SqlParam>)> {
+    let ctx = FilterContext { alias_map, cards, now, deleted };
+    let mut params = Vec::new();
+    let filters = filters
+        .iter()
+        .map(|f| translate_filter_expr(f, &ctx, &mut params).map(|sql| PlanFilter { expression: sql }))
+        .collect::<Result<Vec<_>>>()?;
+    Ok((filters, params))
+}
+
+
+/// The join graph node a field is read from: its role, or its entity.
+fn resolve_node<'a>(field: &str, cards: &'a SchemaCards) -> Option<&'a str> {
+    cards.field(field).map(|f| f.role.as_deref().unwrap_or(&f.entity))
+}
+
+fn build_joins(
+    edges: &[&JoinEdge],
+    alias_map: &HashMap<String, String>,
+) -> Result<Vec<PlanJoin>> {
+    edges
+        .iter()
         .map(|edge| -> Result<PlanJoin> {
             let left_alias = alias_map
                 .get(&edge.from)
@@ -392,8 +1018,8 @@ fn build_joins(
                 .clone();
 
             let right_alias = alias_map
-                .get(&edge.to)
-                .ok_or_else(|| anyhow!("missing alias_map entry for join edge.to '{}'", edge.to))?
+                .get(edge.to_node())
+                .ok_or_else(|| anyhow!("missing alias_map entry for join edge.to '{}'", edge.to_node()))?
                 .clone();
 
             let conditions = edge
@@ -442,61 +1068,232 @@ fn build_joins(
                     _ => JoinType::Inner,
                 },
                 conditions,
+                predicates: vec![],
             })
         })
         .collect::<Result<Vec<_>>>()
 }
 
+/// Soft-delete predicate for one table, or None when the mode is `include` or the entity
+/// has no `Conventions.deleted_column`. Nullable flags use `IS NOT TRUE` so NULL counts
+/// as not deleted.
+fn soft_delete_predicate(cards: &SchemaCards, entity: &str, alias: &str, mode: DeletedMode) -> Option<String> {
+    let deleted_column = &cards.conventions.deleted_column;
+    let column = cards
+        .entities
+        .iter()
+        .find(|e| e.name == entity)?
+        .columns
+        .iter()
+        .find(|c| &c.name == deleted_column)?;
+
+    match mode {
+        DeletedMode::Include => None,
+        DeletedMode::Exclude if column.nullable => Some(format!("{}.{} IS NOT TRUE", alias, column.name)),
+        DeletedMode::Exclude => Some(format!("{}.{} = false", alias, column.name)),
+        DeletedMode::Only => Some(format!("{}.{} = true", alias, column.name)),
+    }
+}
+
+/// Apply the spec's soft-delete mode: the root table in WHERE, and (for `exclude`) joined
+/// tables in their ON clause so outer joins keep their unmatched rows. `only` is root-only;
+/// see `DeletedMode`.
+fn apply_soft_delete(plan: &mut IntermediatePlan, cards: &SchemaCards, mode: DeletedMode) -> Result<()> {
+    let root = plan.root_alias()?;
+    let tables = plan.tables.clone();
+    let predicate_for = |alias: &str| {
+        tables
+            .iter()
+            .find(|t| t.alias == alias)
+            .and_then(|t| soft_delete_predicate(cards, &t.name, &t.alias, mode))
+    };
+
+    if let Some(p) = predicate_for(&root) {
+        plan.filters.insert(0, PlanFilter { expression: p });
+    }
+    if mode == DeletedMode::Exclude {
+        for join in plan.joins.iter_mut() {
+            join.predicates.extend(predicate_for(&join.right_alias));
+        }
+    }
+    Ok(())
+}
+
 /// Stub: compile DSL into an intermediate plan (tables, joins, selected fields, predicates).
 /// In production, this becomes the deterministic backbone that the LLM must follow.
-pub fn compile_report_spec(reg: &SchemaRegistry, spec: &ReportSpec) -> anyhow::Result<IntermediatePlan> {
+///
+/// Fails with the first problem found; `compile_with_diagnostics` reports all of them.
+pub fn compile_report_spec(
+    reg: &SchemaRegistry,
+    spec: &ReportSpec,
+    ctx: &CompileContext,
+) -> anyhow::Result<IntermediatePlan> {
+    compile_with_diagnostics(reg, spec, ctx).map_err(|diagnostics| match diagnostics.into_iter().next() {
+        Some(d) => anyhow::Error::new(d),
+        None => anyhow!("compile failed without a diagnostic"),
+    })
+}
+
+/// Compile a spec, collecting every problem instead of stopping at the first: all
+/// validation errors, or else all join-rule and fan-out errors of the built plan.
+/// Non-fatal findings come back in `plan.warnings`.
+pub fn compile_with_diagnostics(
+    reg: &SchemaRegistry,
+    spec: &ReportSpec,
+    ctx: &CompileContext,
+) -> Result<IntermediatePlan, Vec<Diagnostic>> {
     if reg.index.workspace != spec.workspace {
-        return Err(anyhow::anyhow!(
-            "workspace mismatch: expected {}, found {}",
-            spec.workspace,
-            reg.index.workspace
-        ));
+        let message = format!("workspace mismatch: expected {}, found {}", spec.workspace, reg.index.workspace);
+        return Err(vec![Diagnostic::error("workspace_mismatch", message, "/workspace")]);
+    }
+
+    if ctx.profiles.is_empty() {
+        return Err(vec![(&CompileError::MissingProfile).into()]);
+    }
+
+    let ws = workspace_schema(reg, &spec.workspace);
+    let errors = check_report_spec(spec, ws.as_ref());
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut plan = build_plan(reg, spec, ctx).map_err(|e| {
+        vec![match e.downcast_ref::<CompileError>() {
+            Some(ce) => ce.into(),
+            None => Diagnostic::error("compile_failed", e.to_string(), ""),
+        }]
+    })?;
+
+    let mut errors: Vec<Diagnostic> = check_join_rules(&plan, &reg.cards)
+        .iter()
+        .map(|violation| Diagnostic::error("join_rule", violation.to_string(), ""))
+        .collect();
+    for fan_out in check_fan_out(&plan, spec, &reg.cards) {
+        let pointer = spec
+            .select
+            .iter()
+            .position(|s| fan_out.fields.contains(&s.field))
+            .map_or_else(String::new, |i| format!("/select/{}", i));
+        match ctx.fan_out {
+            FanOutPolicy::Error => errors.push(
+                Diagnostic::error("fan_out", fan_out.to_string(), pointer).with_suggestion(Some(fan_out.suggestion())),
+            ),
+            FanOutPolicy::PreAggregate if pre_aggregate(&mut plan, &fan_out) => {}
+            _ => plan.warnings.push(
+                Diagnostic::warning("fan_out", fan_out.to_string(), pointer).with_suggestion(Some(fan_out.suggestion())),
+            ),
+        }
     }
 
+    if errors.is_empty() {
+        Ok(plan)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Build the plan for a validated spec.
+fn build_plan(reg: &SchemaRegistry, spec: &ReportSpec, ctx: &CompileContext) -> Result<IntermediatePlan> {
     let schema_cards = &reg.cards;
 
-    let select_entities = spec.select.iter().map(|s| resolve_entity(&s.field, schema_cards)).collect::<Vec<_>>();
-    let filter_entities = spec.filters.iter().map(|s| resolve_entity(&s.field, schema_cards)).collect::<Vec<_>>();
-    let order_by_entities = spec.order_by.iter().map(|s| resolve_entity(&s.field, schema_cards)).collect::<Vec<_>>();
-
-    let required_entities = select_entities.iter().chain(filter_entities.iter()).chain(order_by_entities.iter()).collect::<Vec<_>>();
-    let tables = required_entities.iter().filter_map(|e| {
-        e.as_ref().map(|entity| {
-            let alias = match *entity {
-                "offers_latest" => "o",
-                "campaigns_latest" => "c",
-                "campaign_offers" => "co",
-                "offer_products" => "opr",
-                "offer_phases" => "oph",
-                "partners" => "p",
-                other => other,
-            };
-            PlanTable {
-                name: entity.to_string(),
-                alias: alias.to_string()
+    let select_entities = spec.select.iter().map(|s| resolve_node(&s.field, schema_cards));
+    let filter_entities = spec
+        .filters
+        .iter()
+        .flat_map(|f| f.predicates())
+        .map(|s| resolve_node(&s.field, schema_cards));
+    let named_filter_entities = spec
+        .filters
+        .iter()
+        .flat_map(|f| f.named_filters())
+        .map(|n| resolve_named_filter(n, schema_cards).ok().map(|(e, _)| e.name.as_str()));
+    let order_by_entities = spec.order_by.iter().map(|s| resolve_node(&s.field, schema_cards));
+
+    let required_entities = select_entities
+        .chain(filter_entities)
+        .chain(named_filter_entities)
+        .chain(order_by_entities)
+        .flatten()
+        .fold(Vec::<&str>::new(), |mut acc, e| {
+            if !acc.contains(&e) {
+                acc.push(e);
             }
-        })
+            acc
+        });
+
+    // Bridge tables on the connecting paths (e.g. campaign_offers) join the plan after the
+    // entities the spec asked for.
+    let edges = connecting_edges(schema_cards, &required_entities)?;
+    let entities = edges
+        .iter()
+        .flat_map(|e| [e.from.as_str(), e.to_node()])
+        .fold(required_entities, |mut acc, e| {
+            if !acc.contains(&e) {
+                acc.push(e);
+            }
+            acc
+        });
+
+    let mut aliases = AliasAllocator::default();
+    let tables = entities.iter().map(|node| {
+        let entity = schema_cards.node_entity(node);
+        PlanTable {
+            name: entity.to_string(),
+            alias: aliases.allocate(&preferred_alias(schema_cards, node)),
+            role: (entity != *node).then(|| node.to_string()),
+            subquery: None,
+        }
     }).collect::<Vec<_>>();
-    let alias_map: HashMap<String, String> = tables.iter().map(|t| (t.name.clone(), t.alias.clone())).collect();
-    let joins = build_joins(&reg.cards, required_entities, &alias_map)?;
+    let alias_map: HashMap<String, String> = tables.iter().map(|t| (t.node().to_string(), t.alias.clone())).collect();
+    let joins = build_joins(&edges, &alias_map)?;
     let projections = translate_projections(&spec.select, &alias_map, &reg.cards)?;
-    let filters = translate_filters(&spec.filters, &alias_map, &reg.cards)?;
-    let order_by = translate_ordering(&spec.order_by, &alias_map, &reg.cards)?;
-    let (limit, offset) = compile_pagination(&spec)?;
-    let plan = IntermediatePlan {
+    let now = ctx.clock.now();
+    let (filters, mut params) = translate_filters(&spec.filters, &alias_map, &reg.cards, now, spec.deleted)?;
+    let group_by = translate_group_by(spec, &alias_map, &reg.cards)?;
+    let having = translate_having(&spec.having, &alias_map, &reg.cards, now, &mut params)?;
+    let (projections, rank, order_by) = match &spec.rank {
+        None => (projections, None, translate_ordering(&spec.order_by, &spec.select, &alias_map, &reg.cards)?),
+        Some(rank) => {
+            // Every column gets a name so the outer query can read it from the CTE.
+            let projections: Vec<PlanProjection> = projections
+                .into_iter()
+                .zip(&spec.select)
+                .map(|(p, item)| PlanProjection { alias: Some(output_name(item)), ..p })
+                .collect();
+            let root = tables
+                .iter()
+                .find(|t| !joins.iter().any(|j| j.right_alias == t.alias))
+                .ok_or_else(|| anyhow!("plan has no root table"))?;
+            let tiebreaker = rank_tiebreaker(&group_by, root, &reg.cards)?;
+            let plan_rank = translate_rank(rank, &spec.select, &projections, &tiebreaker, &alias_map, &reg.cards)?;
+            let order_by =
+                translate_ranked_ordering(&spec.order_by, &spec.select, &projections, &plan_rank, &tiebreaker)?;
+            (projections, Some(plan_rank), order_by)
+        }
+    };
+    let (limit, offset) = compile_pagination(spec, &reg.index.row_limits)?;
+    let mut plan = IntermediatePlan {
         workspace: spec.workspace.clone(),
         tables,
         joins,
         projections,
         filters,
+        group_by,
+        having,
+        rank,
         order_by,
         limit,
-        offset
+        offset,
+        profile_scope: None,
+        params,
+        warnings: vec![],
     };
+    plan.profile_scope = Some(PlanProfileScope {
+        alias: plan.root_alias()?,
+        column: schema_cards.conventions.profile_column.clone(),
+        profiles: ctx.profiles.clone(),
+    });
+    enforce_profile_isolation(&plan)?;
+    apply_soft_delete(&mut plan, schema_cards, spec.deleted)?;
     Ok(plan)
 }
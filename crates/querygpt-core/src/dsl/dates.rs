@@ -0,0 +1,167 @@
+use std::fmt;
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, SecondsFormat, Utc};
+
+/// Source of "now" for relative date filters. Injected through `CompileContext` so the
+/// same spec compiles to the same plan under test.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Days,
+    Weeks,
+    Months,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Week, // ISO weeks, starting Monday
+    Month,
+    Quarter,
+    Year,
+}
+
+/// A relative date expression used as the value of a `relative` filter, e.g.
+/// `today`, `last_30_days`, `next_2_weeks`, `this_quarter`, `last_month`, `before_now`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelativeDate {
+    Today,
+    Last(u32, Unit),  // the N units ending today, today included
+    Next(u32, Unit),  // the N units starting today, today included
+    This(Period),     // the calendar period containing today
+    Previous(Period), // the calendar period before this one
+    BeforeNow,
+    AfterNow,
+}
+
+/// A resolved relative date: a half-open day range, or a bound on the current instant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateBound {
+    Range { start: NaiveDate, end: NaiveDate }, // start <= d < end
+    Before(DateTime<Utc>),
+    After(DateTime<Utc>),
+}
+
+impl RelativeDate {
+    pub fn parse(s: &str) -> Option<Self> {
+        let period = |p: &str| match p {
+            "week" => Some(Period::Week),
+            "month" => Some(Period::Month),
+            "quarter" => Some(Period::Quarter),
+            "year" => Some(Period::Year),
+            _ => None,
+        };
+
+        match s.split('_').collect::<Vec<_>>().as_slice() {
+            ["today"] => Some(RelativeDate::Today),
+            ["before", "now"] => Some(RelativeDate::BeforeNow),
+            ["after", "now"] => Some(RelativeDate::AfterNow),
+            ["this", p] => period(p).map(RelativeDate::This),
+            ["last", p] => period(p).map(RelativeDate::Previous),
+            [dir @ ("last" | "next"), n, unit] => {
+                let n = n.parse::<u32>().ok().filter(|n| *n > 0)?;
+                let unit = match *unit {
+                    "days" => Unit::Days,
+                    "weeks" => Unit::Weeks,
+                    "months" => Unit::Months,
+                    _ => return None,
+                };
+                Some(if *dir == "last" { RelativeDate::Last(n, unit) } else { RelativeDate::Next(n, unit) })
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolve against `now` (UTC). Returns None only if the range overflows the calendar.
+    pub fn resolve(self, now: DateTime<Utc>) -> Option<DateBound> {
+        let today = now.date_naive();
+        let range = |start: NaiveDate, end: NaiveDate| Some(DateBound::Range { start, end });
+
+        match self {
+            RelativeDate::Today => range(today, today.succ_opt()?),
+            RelativeDate::Last(n, unit) => {
+                let end = today.succ_opt()?;
+                range(sub_units(end, n, unit)?, end)
+            }
+            RelativeDate::Next(n, unit) => range(today, add_units(today, n, unit)?),
+            RelativeDate::This(period) => {
+                let start = period_start(today, period)?;
+                range(start, add_period(start, period)?)
+            }
+            RelativeDate::Previous(period) => {
+                let end = period_start(today, period)?;
+                range(sub_period(end, period)?, end)
+            }
+            RelativeDate::BeforeNow => Some(DateBound::Before(now)),
+            RelativeDate::AfterNow => Some(DateBound::After(now)),
+        }
+    }
+}
+
+/// RFC 3339 form used for timestamp bind values, e.g. `2025-06-15T12:00:00Z`.
+pub fn format_timestamp(ts: DateTime<Utc>) -> String {
+    ts.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn add_units(d: NaiveDate, n: u32, unit: Unit) -> Option<NaiveDate> {
+    match unit {
+        Unit::Days => d.checked_add_days(Days::new(n.into())),
+        Unit::Weeks => d.checked_add_days(Days::new(u64::from(n) * 7)),
+        Unit::Months => d.checked_add_months(Months::new(n)),
+    }
+}
+
+fn sub_units(d: NaiveDate, n: u32, unit: Unit) -> Option<NaiveDate> {
+    match unit {
+        Unit::Days => d.checked_sub_days(Days::new(n.into())),
+        Unit::Weeks => d.checked_sub_days(Days::new(u64::from(n) * 7)),
+        Unit::Months => d.checked_sub_months(Months::new(n)),
+    }
+}
+
+fn period_start(d: NaiveDate, period: Period) -> Option<NaiveDate> {
+    match period {
+        Period::Week => d.checked_sub_days(Days::new(d.weekday().num_days_from_monday().into())),
+        Period::Month => d.with_day(1),
+        Period::Quarter => NaiveDate::from_ymd_opt(d.year(), (d.month0() / 3) * 3 + 1, 1),
+        Period::Year => NaiveDate::from_ymd_opt(d.year(), 1, 1),
+    }
+}
+
+fn period_units(period: Period) -> (u32, Unit) {
+    match period {
+        Period::Week => (1, Unit::Weeks),
+        Period::Month => (1, Unit::Months),
+        Period::Quarter => (3, Unit::Months),
+        Period::Year => (12, Unit::Months),
+    }
+}
+
+fn add_period(d: NaiveDate, period: Period) -> Option<NaiveDate> {
+    let (n, unit) = period_units(period);
+    add_units(d, n, unit)
+}
+
+fn sub_period(d: NaiveDate, period: Period) -> Option<NaiveDate> {
+    let (n, unit) = period_units(period);
+    sub_units(d, n, unit)
+}
@@ -0,0 +1,49 @@
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One finding about a ReportSpec, from validation or compilation.
+///
+/// `pointer` is a JSON pointer (RFC 6901) into the spec as submitted, e.g.
+/// `/filters/0/all/1/op`; it is empty when the finding is about the spec as a whole or
+/// about the compile context.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Error)]
+#[error("{message}")]
+pub struct Diagnostic {
+    pub code: &'static str, // stable identifier, e.g. "unknown_field"
+    pub severity: Severity,
+    pub message: String,
+    pub pointer: String,
+    pub suggestion: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, pointer: impl Into<String>) -> Self {
+        Self {
+            code,
+            severity: Severity::Error,
+            message: message.into(),
+            pointer: pointer.into(),
+            suggestion: None,
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>, pointer: impl Into<String>) -> Self {
+        Self { severity: Severity::Warning, ..Self::error(code, message, pointer) }
+    }
+
+    pub fn with_suggestion(mut self, suggestion: Option<String>) -> Self {
+        self.suggestion = suggestion;
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::BTreeSet;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::dsl::plan::{IntermediatePlan, PlanJoin};
+use crate::dsl::report_spec::{Aggregate, ReportSpec};
+use crate::schema::cards::SchemaCards;
+use crate::schema::join_graph::find_edge;
+
+/// What the compiler does when a `1:n`/`n:n` join would repeat rows the report reads.
+/// - `warn`: compile anyway and record the fan-out in `IntermediatePlan.warnings`
+/// - `error`: reject the spec
+/// - `pre_aggregate`: when the many side is a single table used only by filters, join a
+///   `SELECT DISTINCT` of its join keys instead; any other fan-out is recorded as under `warn`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FanOutPolicy {
+    #[default]
+    Warn,
+    Error,
+    PreAggregate,
+}
+
+/// A join that multiplies rows of `one` by the matching rows of `many`, so `fields` would
+/// come back duplicated (or, for `count`/`sum`/`avg`, inflated).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Error)]
+#[error(
+    "joining {many} to {one} ({cardinality}) repeats each {one} row per matching {many} row, duplicating {}",
+    fields.join(", ")
+)]
+pub struct FanOut {
+    pub one: String,
+    pub many: String,
+    pub cardinality: String,
+    pub fields: Vec<String>,
+}
+
+impl FanOut {
+    pub fn suggestion(&self) -> String {
+        format!("select or group by a {} field, aggregate, or use an exists filter instead", self.many)
+    }
+}
+
+/// True when `expr` reads a column through `alias`, e.g. `o.id` but not `co.id`.
+pub(crate) fn references_alias(expr: &str, alias: &str) -> bool {
+    let needle = format!("{}.", alias);
+    expr.match_indices(&needle).any(|(i, _)| {
+        !expr[..i]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// Aliases reachable from `start` without crossing `cut`: one side of the join tree.
+fn side_of(plan: &IntermediatePlan, cut: &PlanJoin, start: &str) -> BTreeSet<String> {
+    let mut side: BTreeSet<String> = [start.to_string()].into_iter().collect();
+    let mut frontier = vec![start.to_string()];
+    while let Some(alias) = frontier.pop() {
+        for j in plan.joins.iter().filter(|j| !std::ptr::eq(*j, cut)) {
+            let next = if j.left_alias == alias {
+                &j.right_alias
+            } else if j.right_alias == alias {
+                &j.left_alias
+            } else {
+                continue;
+            };
+            if side.insert(next.clone()) {
+                frontier.push(next.clone());
+            }
+        }
+    }
+    side
+}
+
+/// Check every join of a plan for row fan-out, tracking the report's grain through the
+/// join tree.
+///
+/// The grain is the non-aggregated projections, or the GROUP BY keys of a grouped report.
+/// Each `1:n` (and both directions of each `n:n`) edge repeats the rows on its one side
+/// once per row on its many side. That is harmless when the grain reaches into the many
+/// side; otherwise the projections on the one side are duplicated. Grouped reports only
+/// suffer through `count`, `sum` and `avg`; `count_distinct`, `min`, `max` and the
+/// `DISTINCT` string_agg ignore repeats.
+///
+/// Returns every fan-out, in plan join order.
+pub fn check_fan_out(plan: &IntermediatePlan, spec: &ReportSpec, cards: &SchemaCards) -> Vec<FanOut> {
+    let table_name = |alias: &str| plan.tables.iter().find(|t| t.alias == alias).map(|t| t.node().to_string());
+    let aggregated = !plan.group_by.is_empty();
+
+    let grain: Vec<&str> = if aggregated {
+        plan.group_by.iter().map(|g| g.expression.as_str()).collect()
+    } else {
+        plan.projections.iter().map(|p| p.expression.as_str()).collect()
+    };
+    let affected: Vec<(&str, &str)> = plan
+        .projections
+        .iter()
+        .zip(&spec.select)
+        .filter(|(_, item)| {
+            !aggregated || matches!(item.agg, Some(Aggregate::Count | Aggregate::Sum | Aggregate::Avg))
+        })
+        .map(|(p, item)| (item.field.as_str(), p.expression.as_str()))
+        .collect();
+    let reads = |exprs: &[&str], side: &BTreeSet<String>| {
+        exprs.iter().any(|e| side.iter().any(|a| references_alias(e, a)))
+    };
+
+    let mut fan_outs = Vec::new();
+    for join in &plan.joins {
+        let Some((from, to)) = table_name(&join.left_alias).zip(table_name(&join.right_alias)) else {
+            continue;
+        };
+        let Some(edge) = find_edge(cards, &from, &to) else {
+            continue;
+        };
+        let directions = match edge.cardinality.as_str() {
+            "1:n" => vec![(&join.left_alias, &join.right_alias)],
+            "n:1" => vec![(&join.right_alias, &join.left_alias)],
+            "n:n" => vec![(&join.left_alias, &join.right_alias), (&join.right_alias, &join.left_alias)],
+            _ => vec![],
+        };
+
+        for (one, many) in directions {
+            let many_side = side_of(plan, join, many);
+            if reads(&grain, &many_side) {
+                continue;
+            }
+            let one_side = side_of(plan, join, one);
+            let fields: Vec<String> = affected
+                .iter()
+                .filter(|(_, expr)| reads(&[expr], &one_side))
+                .map(|(field, _)| field.to_string())
+                .collect();
+            if !fields.is_empty() {
+                fan_outs.push(FanOut {
+                    one: table_name(one).unwrap_or_default(),
+                    many: table_name(many).unwrap_or_default(),
+                    cardinality: edge.cardinality.clone(),
+                    fields,
+                });
+            }
+        }
+    }
+    fan_outs
+}
+
+/// Replace the many side of `fan_out` with a `SELECT DISTINCT` of its join keys, moving
+/// the filters and soft-delete predicates on it inside, so it contributes at most one row
+/// per one-side row. Only possible when the many side is a single joined-to table that
+/// nothing but top-level filters reads; returns false (leaving the plan untouched) otherwise.
+pub fn pre_aggregate(plan: &mut IntermediatePlan, fan_out: &FanOut) -> bool {
+    let Some(table) = plan.tables.iter().find(|t| t.node() == fan_out.many) else {
+        return false;
+    };
+    let alias = table.alias.clone();
+    let name = table.name.clone();
+    let Some(index) = plan.joins.iter().position(|j| j.right_alias == alias) else {
+        return false;
+    };
+    let join = &plan.joins[index];
+    if plan.joins.iter().any(|j| j.left_alias == alias) {
+        return false;
+    }
+
+    let other_aliases: Vec<&str> = plan.tables.iter().map(|t| t.alias.as_str()).filter(|a| *a != alias).collect();
+    let only_many = |expr: &str| {
+        references_alias(expr, &alias) && !other_aliases.iter().any(|a| references_alias(expr, a))
+    };
+    let read_elsewhere = plan
+        .projections
+        .iter()
+        .map(|p| p.expression.as_str())
+        .chain(plan.group_by.iter().map(|g| g.expression.as_str()))
+        .chain(plan.having.iter().map(|h| h.expression.as_str()))
+        .chain(plan.order_by.iter().map(|o| o.expression.as_str()))
+        .chain(plan.rank.iter().flat_map(|r| {
+            r.partition_by.iter().map(String::as_str).chain(r.order_by.iter().map(|o| o.expression.as_str()))
+        }))
+        .chain(plan.filters.iter().map(|f| f.expression.as_str()).filter(|e| !only_many(e)))
+        .any(|e| references_alias(e, &alias));
+    if read_elsewhere {
+        return false;
+    }
+
+    let keys = join.conditions.iter().map(|c| c.right_field.clone()).collect::<Vec<_>>();
+    let (moved, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut plan.filters)
+        .into_iter()
+        .partition(|f| only_many(&f.expression));
+    let predicates = join
+        .predicates
+        .iter()
+        .cloned()
+        .chain(moved.into_iter().map(|f| f.expression))
+        .collect::<Vec<_>>();
+
+    let mut subquery = format!("SELECT DISTINCT {} FROM {} {}", keys.join(", "), name, alias);
+    if !predicates.is_empty() {
+        subquery.push_str(&format!(" WHERE {}", predicates.join(" AND ")));
+    }
+    plan.filters = kept;
+    plan.joins[index].predicates.clear();
+    if let Some(t) = plan.tables.iter_mut().find(|t| t.alias == alias) {
+        t.subquery = Some(subquery);
+    }
+    true
+}
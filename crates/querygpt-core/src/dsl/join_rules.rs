@@ -0,0 +1,91 @@
+use crate::dsl::plan::{IntermediatePlan, PlanJoin};
+use crate::schema::cards::{SchemaCards, VersionRule};
+use crate::schema::join_graph::{assert_edge_safe, find_edge};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum JoinRuleError {
+    #[error("join {left_alias} -> {right_alias} references an alias missing from plan.tables")]
+    UnknownAlias { left_alias: String, right_alias: String },
+
+    #[error("no join edge {from} -> {to} in the join graph")]
+    UnknownEdge { from: String, to: String },
+
+    #[error("join edge {from} -> {to} is marked unsafe")]
+    UnsafeEdge { from: String, to: String },
+
+    #[error("join {from} -> {to} must match '{column}' on both sides")]
+    MissingProfile { from: String, to: String, column: String },
+
+    #[error("join {from} -> {to} must match '{column}' on both sides (version-aligned edge)")]
+    MissingVersion { from: String, to: String, column: String },
+
+    #[error("join {from} -> {to} must not reference '{column}' (versions are independent)")]
+    ForbiddenVersion { from: String, to: String, column: String },
+}
+
+fn column_of(field: &str) -> &str {
+    field.split_once('.').map(|(_, col)| col).unwrap_or(field)
+}
+
+/// True when some condition equates `column` on both sides of the join.
+fn matches_column(join: &PlanJoin, column: &str) -> bool {
+    join.conditions
+        .iter()
+        .any(|c| column_of(&c.left_field) == column && column_of(&c.right_field) == column)
+}
+
+/// True when any condition touches `column` on either side of the join.
+fn references_column(join: &PlanJoin, column: &str) -> bool {
+    join.conditions
+        .iter()
+        .any(|c| column_of(&c.left_field) == column || column_of(&c.right_field) == column)
+}
+
+fn check_join(plan: &IntermediatePlan, join: &PlanJoin, cards: &SchemaCards) -> Result<(), JoinRuleError> {
+    let table_name = |alias: &str| plan.tables.iter().find(|t| t.alias == alias).map(|t| t.node().to_string());
+    let (from, to) = table_name(&join.left_alias)
+        .zip(table_name(&join.right_alias))
+        .ok_or_else(|| JoinRuleError::UnknownAlias {
+            left_alias: join.left_alias.clone(),
+            right_alias: join.right_alias.clone(),
+        })?;
+
+    let edge = find_edge(cards, &from, &to).ok_or_else(|| JoinRuleError::UnknownEdge {
+        from: from.clone(),
+        to: to.clone(),
+    })?;
+    assert_edge_safe(cards, &from, &to).map_err(|_| JoinRuleError::UnsafeEdge {
+        from: from.clone(),
+        to: to.clone(),
+    })?;
+
+    let profile = &cards.conventions.profile_column;
+    if !matches_column(join, profile) {
+        return Err(JoinRuleError::MissingProfile { from, to, column: profile.clone() });
+    }
+
+    let version = &cards.conventions.version_column;
+    match edge.version_rule {
+        VersionRule::Match if !matches_column(join, version) => {
+            Err(JoinRuleError::MissingVersion { from, to, column: version.clone() })
+        }
+        VersionRule::Forbid if references_column(join, version) => {
+            Err(JoinRuleError::ForbiddenVersion { from, to, column: version.clone() })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check every join of a plan against the workspace join rules:
+/// - the edge exists in the join graph and is marked safe
+/// - the join equates `Conventions.profile_column`
+/// - the edge's `version_rule` holds for `Conventions.version_column`
+///
+/// Returns every violation, in plan join order.
+pub fn check_join_rules(plan: &IntermediatePlan, cards: &SchemaCards) -> Vec<JoinRuleError> {
+    plan.joins
+        .iter()
+        .filter_map(|j| check_join(plan, j, cards).err())
+        .collect()
+}
@@ -2,4 +2,9 @@ pub mod report_spec;
 pub mod compile;
 pub mod validate;
 pub mod plan;
+pub mod join_rules;
+pub mod fan_out;
+pub mod diagnostics;
+pub mod dates;
+pub mod aliases;
 
@@ -1,19 +1,37 @@
+use std::collections::BTreeSet;
+use anyhow::{anyhow, Result};
 use serde::Serialize;
+use crate::dsl::diagnostics::Diagnostic;
 
 // Each table used in the query, with an alias
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct PlanTable {
     pub name: String,      // e.g. "offers_latest"
     pub alias: String,     // e.g. "o"
+    pub role: Option<String>,     // join graph role for a second instance of `name`
+    pub subquery: Option<String>, // pre-aggregated derived table joined in place of `name`
+}
+
+impl PlanTable {
+    /// A plain table instance: no role, no subquery.
+    pub fn new(name: impl Into<String>, alias: impl Into<String>) -> Self {
+        Self { name: name.into(), alias: alias.into(), ..Self::default() }
+    }
+
+    /// The join graph node this table instance is: its role, or its entity.
+    pub fn node(&self) -> &str {
+        self.role.as_deref().unwrap_or(&self.name)
+    }
 }
 
 // A single join between two tables
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct PlanJoin {
     pub left_alias: String,    // alias of left table, e.g. "o"
     pub right_alias: String,   // alias of right table, e.g. "c"
     pub join_type: JoinType,   // Inner, Left, etc.
     pub conditions: Vec<JoinCondition>,  // list of equality predicates
+    pub predicates: Vec<String>,         // extra ON predicates, e.g. "p.deleted IS NOT TRUE"
 }
 
 // Equality predicate for a join
@@ -24,8 +42,9 @@ pub struct JoinCondition {
 }
 
 // Type of join (inner, left)
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub enum JoinType {
+    #[default]
     Inner,
     Left,
 }
@@ -41,7 +60,48 @@ pub struct PlanProjection {
 // A filter predicate in the WHERE clause
 #[derive(Debug, Clone, Serialize)]
 pub struct PlanFilter {
-    pub expression: String,   // actual SQL, e.g. "o.status = 'PUBLISHED'"
+    pub expression: String,   // actual SQL with placeholders, e.g. "o.status = $2"
+}
+
+// A typed bind value for a `$n` placeholder
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SqlParam {
+    Text(String),
+    Date(String),            // ISO date, bound as a date by the executor
+    Timestamp(String),       // RFC 3339 UTC instant, bound as a timestamptz by the executor
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    TextArray(Vec<String>),  // only used for the profile scope, e.g. `= ANY($1)`
+}
+
+impl SqlParam {
+    /// The value as an inline SQL literal. For display and snapshots only; never execute it.
+    pub fn to_sql_literal(&self) -> String {
+        fn quote(s: &str) -> String {
+            format!("'{}'", s.replace('\'', "''"))
+        }
+        match self {
+            SqlParam::Text(s) | SqlParam::Date(s) | SqlParam::Timestamp(s) => quote(s),
+            SqlParam::Int(n) => n.to_string(),
+            SqlParam::Float(n) => n.to_string(),
+            SqlParam::Bool(b) => b.to_string(),
+            SqlParam::TextArray(items) => {
+                format!("ARRAY[{}]", items.iter().map(|s| quote(s)).collect::<Vec<_>>().join(", "))
+            }
+        }
+    }
+}
+
+// $1 is always the profile scope; filter parameters are numbered from here
+pub const FIRST_FILTER_PARAM: usize = 2;
+
+// A grouping key in the GROUP BY clause
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanGroupBy {
+    pub field: String,        // workspace field name, e.g. "offer_id"
+    pub expression: String,   // actual SQL expression, e.g. "o.id"
 }
 
 // A sort directive in the ORDER BY clause
@@ -51,22 +111,82 @@ pub struct PlanOrder {
     pub direction: SortDirection,
 }
 
+// ROW_NUMBER() over the grouped/filtered rows; the query is wrapped in a `ranked` CTE
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanRank {
+    pub partition_by: Vec<String>, // SQL expressions, e.g. "c.id"
+    pub order_by: Vec<PlanOrder>,  // SQL expressions inside the window
+    pub alias: String,             // output column, e.g. "rank"
+    pub top_n: Option<u64>,        // keep rows with rank <= top_n
+    // Partition and tiebreaker columns no select item outputs; the `ranked` CTE carries them
+    // for the outer ORDER BY only, e.g. "c.id AS rank_key_1".
+    pub keys: Vec<PlanProjection>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub enum SortDirection {
     Asc,
     Desc,
 }
 
-// The overall intermediate plan
+// Profile isolation on the root table; the profiles are bound as $1
 #[derive(Debug, Clone, Serialize)]
+pub struct PlanProfileScope {
+    pub alias: String,         // root alias, e.g. "o"
+    pub column: String,        // Conventions.profile_column, e.g. "profile"
+    pub profiles: Vec<String>, // one profile renders `= $1`, several `= ANY($1)`
+}
+
+// The overall intermediate plan
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct IntermediatePlan {
     pub workspace: String,          // e.g. "campaigns_offers"
     pub tables: Vec<PlanTable>,
     pub joins: Vec<PlanJoin>,
     pub projections: Vec<PlanProjection>,
     pub filters: Vec<PlanFilter>,
+    pub group_by: Vec<PlanGroupBy>, // empty unless the report aggregates
+    pub having: Vec<PlanFilter>,    // predicates on aggregates, ANDed after GROUP BY
+    pub rank: Option<PlanRank>,     // when set, order_by refers to the CTE's output columns
     pub order_by: Vec<PlanOrder>,
 
     pub limit: Option<u64>,
     pub offset: Option<u64>,
+    pub profile_scope: Option<PlanProfileScope>,
+    pub params: Vec<SqlParam>,      // bind values for $2..$n, in placeholder order
+    pub warnings: Vec<Diagnostic>,  // non-fatal compile findings, e.g. row fan-out
+}
+
+impl IntermediatePlan {
+    /// The table the FROM clause starts at: never joined TO, and preferably the start of a
+    /// join chain. Deterministic for a given set of tables and joins.
+    pub fn root_alias(&self) -> Result<String> {
+        let table_aliases: BTreeSet<String> =
+            self.tables.iter().map(|t| t.alias.clone()).collect();
+
+        let right_aliases: BTreeSet<String> =
+            self.joins.iter().map(|j| j.right_alias.clone()).collect();
+
+        let left_aliases: BTreeSet<String> =
+            self.joins.iter().map(|j| j.left_alias.clone()).collect();
+
+        // First try: tables that are never joined TO (traditional root selection)
+        let traditional_roots: BTreeSet<String> = table_aliases.difference(&right_aliases).cloned().collect();
+
+        // If we have joins, prefer a root that appears as a left_alias (can start a join chain)
+        if !self.joins.is_empty() {
+            let viable_roots: BTreeSet<String> = traditional_roots.intersection(&left_aliases).cloned().collect();
+
+            if let Some(root) = viable_roots.iter().next() {
+                return Ok(root.clone());
+            }
+        }
+
+        // Fallback to any traditional root
+        traditional_roots
+            .iter()
+            .next()
+            .cloned()
+            .ok_or_else(|| anyhow!("cannot determine root alias: plan has no tables or join graph is cyclic"))
+    }
 }
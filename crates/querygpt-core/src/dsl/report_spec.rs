@@ -1,5 +1,7 @@
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use crate::schema::field_catalog::FieldType;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ReportSpec {
@@ -7,12 +9,20 @@ pub struct ReportSpec {
     pub workspace: String,
     pub select: Vec<SelectItem>,
     #[serde(default)]
-    pub filters: Vec<Filter>,
+    pub filters: Vec<FilterExpr>,
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    #[serde(default)]
+    pub having: Vec<HavingFilter>,
+    #[serde(default)]
+    pub rank: Option<RankSpec>,
     #[serde(default)]
     pub order_by: Vec<OrderBy>,
     #[serde(default = "default_mode")]
     pub mode: Mode,
     pub pagination: Option<PaginationSpec>,
+    #[serde(default)]
+    pub deleted: DeletedMode,
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize, PartialEq, Eq)]
@@ -30,29 +40,196 @@ pub struct SelectItem {
     pub field: String,
     #[serde(default)]
     pub alias: Option<String>,
+    #[serde(default)]
+    pub agg: Option<Aggregate>,
+    #[serde(default)]
+    pub date_trunc: Option<DateTrunc>,
+}
+
+/// Bucket a date field to the start of its day/week/month/quarter/year. A bucketed field
+/// is grouped and ordered by its bucket, e.g. "offers expiring per month".
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DateTrunc {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+/// Aggregate applied to a selected field. Selecting any aggregate groups the report by
+/// `group_by`, or by every non-aggregated select field when `group_by` is empty.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregate {
+    Count,
+    CountDistinct,
+    Sum,
+    Min,
+    Max,
+    Avg,
+    StringAgg,
+}
+
+impl Aggregate {
+    /// The type of the aggregated value for an input field of type `input`.
+    pub fn result_type(self, input: FieldType) -> FieldType {
+        match self {
+            Aggregate::Count | Aggregate::CountDistinct | Aggregate::Sum | Aggregate::Avg => FieldType::Number,
+            Aggregate::Min | Aggregate::Max => input,
+            Aggregate::StringAgg => FieldType::String,
+        }
+    }
 }
 
+/// A predicate on a field. `args` fill the params of a derived field, e.g.
+/// `{"field": "expires_within", "args": {"days": 7}, "op": "eq", "value": true}`; params
+/// left out use their defaults.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Filter {
     pub field: String,
     pub op: FilterOp,
+    #[serde(default)]
+    pub value: Value, // omitted (null) for is_null / is_not_null
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub args: BTreeMap<String, Value>,
+}
+
+/// A predicate on an aggregated select item, applied after grouping. `field` and `agg`
+/// must match a select item; `agg` is omitted for fields that are aggregates themselves
+/// (e.g. `products_csv`). Entries are ANDed together.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HavingFilter {
+    pub field: String,
+    #[serde(default)]
+    pub agg: Option<Aggregate>,
+    pub op: FilterOp,
+    #[serde(default)]
     pub value: Value,
 }
 
+/// A filter predicate or a boolean group of filters.
+/// Top-level `filters` are ANDed together; groups nest:
+/// `{"any": [...]}`, `{"all": [...]}`, `{"not": {...}}`,
+/// `{"exists": {...}}`, `{"not_exists": {...}}`, and `{"named": ...}` for an entity's
+/// common filter.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum FilterExpr {
+    All { all: Vec<FilterExpr> },
+    Any { any: Vec<FilterExpr> },
+    Not { not: Box<FilterExpr> },
+    Exists { exists: ExistsFilter },
+    NotExists { not_exists: ExistsFilter },
+    Named(NamedFilter),
+    Predicate(Filter),
+}
+
+/// One of the `common_filters` of an entity card, e.g. `{"named": "phase_type_prepaid"}`.
+/// `entity` picks the card when several declare the name; `params` fill the filter's
+/// `:name` slots, e.g. `{"named": "countries_any_of", "params": {"countries": ["AU", "NZ"]}}`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NamedFilter {
+    pub named: String,
+    #[serde(default)]
+    pub entity: Option<String>,
+    #[serde(default)]
+    pub params: BTreeMap<String, Value>,
+}
+
+/// A related `entity` row that must (or must not) exist, optionally matching `filters` on
+/// that entity's fields. Compiled into a correlated EXISTS subquery, so the entity is never
+/// joined into the report and cannot fan out its rows.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExistsFilter {
+    pub entity: String,
+    #[serde(default)]
+    pub filters: Vec<FilterExpr>,
+}
+
+impl FilterExpr {
+    /// Every leaf predicate of the expression evaluated by the outer query, depth first.
+    /// Predicates inside `exists`/`not_exists` belong to their subquery and are skipped.
+    pub fn predicates(&self) -> Vec<&Filter> {
+        match self {
+            FilterExpr::All { all: children } | FilterExpr::Any { any: children } => {
+                children.iter().flat_map(|c| c.predicates()).collect()
+            }
+            FilterExpr::Not { not } => not.predicates(),
+            FilterExpr::Exists { .. } | FilterExpr::NotExists { .. } | FilterExpr::Named(_) => vec![],
+            FilterExpr::Predicate(f) => vec![f],
+        }
+    }
+
+    /// Every named filter of the expression evaluated by the outer query, depth first.
+    pub fn named_filters(&self) -> Vec<&NamedFilter> {
+        match self {
+            FilterExpr::All { all: children } | FilterExpr::Any { any: children } => {
+                children.iter().flat_map(|c| c.named_filters()).collect()
+            }
+            FilterExpr::Not { not } => not.named_filters(),
+            FilterExpr::Exists { .. } | FilterExpr::NotExists { .. } | FilterExpr::Predicate(_) => vec![],
+            FilterExpr::Named(n) => vec![n],
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct OrderBy {
     pub field: String,
     pub dir: SortDir,
 }
 
+/// Number rows within each partition with ROW_NUMBER() and optionally keep the first
+/// `top_n`, e.g. "latest 3 offers per campaign". The rank is returned as column `alias`;
+/// the report's own `order_by` then sorts the ranked rows and may only use selected fields.
+/// Ties on the rank's keys are broken by the root entity's primary key (the group keys of
+/// an aggregated report), and the output keeps each partition together in rank order.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RankSpec {
+    #[serde(default)]
+    pub partition_by: Vec<String>,
+    pub order_by: Vec<RankOrder>,
+    #[serde(default)]
+    pub top_n: Option<u64>,
+    #[serde(default = "default_rank_alias")]
+    pub alias: String,
+}
+
+fn default_rank_alias() -> String {
+    "rank".to_string()
+}
+
+/// A rank ordering key; `agg` ranks grouped rows by an aggregate, e.g. offer count.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RankOrder {
+    pub field: String,
+    #[serde(default)]
+    pub agg: Option<Aggregate>,
+    pub dir: SortDir,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum FilterOp {
     Eq,
+    Neq,
     In,
+    NotIn,
     Overlaps,
+    Contains,   // array contains all of the given values
+    Gt,
     Gte,
+    Lt,
     Lte,
+    Between,    // value: [low, high], inclusive
+    IsNull,
+    IsNotNull,
+    Relative,   // value: a relative date such as "last_30_days", resolved at compile time
+    StartsWith, // case-sensitive prefix; LIKE wildcards in the value are matched literally
+    #[serde(rename = "ilike")]
+    ILike,      // case-insensitive LIKE pattern: `%` and `_` are wildcards, `\` makes the next char literal
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -69,13 +246,51 @@ pub enum Mode {
     Export,
 }
 
+/// How soft-deleted rows (`Conventions.deleted_column`) are treated.
+/// - `exclude`: every table in the plan that has the column drops deleted rows
+/// - `include`: no filtering
+/// - `only`: the root table keeps only deleted rows. Joined tables are not filtered, since a
+///   deleted offer's links are usually deleted (or not) independently, and `exists` /
+///   `not_exists` subqueries still drop deleted rows, as they would under `exclude`
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletedMode {
+    #[default]
+    Exclude,
+    Include,
+    Only,
+}
+
 /// Makes the spec stable for snapshot tests and caching.
 /// - preserves `select` order (important for exports)
-/// - sorts filters deterministically
+/// - sorts filters deterministically, including the children of `all`/`any` groups
 pub fn normalize(mut spec: ReportSpec) -> ReportSpec {
-    spec.filters.sort_by(|a, b| {
-        (a.field.as_str(), &a.op as *const _ as usize)
-            .cmp(&(b.field.as_str(), &b.op as *const _ as usize))
-    });
+    spec.filters = normalize_filters(spec.filters);
     spec
 }
+
+fn normalize_filters(filters: Vec<FilterExpr>) -> Vec<FilterExpr> {
+    let mut filters = filters.into_iter().map(normalize_filter).collect::<Vec<_>>();
+    filters.sort_by_cached_key(filter_sort_key);
+    filters
+}
+
+fn normalize_exists(exists: ExistsFilter) -> ExistsFilter {
+    ExistsFilter { entity: exists.entity, filters: normalize_filters(exists.filters) }
+}
+
+fn normalize_filter(expr: FilterExpr) -> FilterExpr {
+    match expr {
+        FilterExpr::All { all } => FilterExpr::All { all: normalize_filters(all) },
+        FilterExpr::Any { any } => FilterExpr::Any { any: normalize_filters(any) },
+        FilterExpr::Not { not } => FilterExpr::Not { not: Box::new(normalize_filter(*not)) },
+        FilterExpr::Exists { exists } => FilterExpr::Exists { exists: normalize_exists(exists) },
+        FilterExpr::NotExists { not_exists } => FilterExpr::NotExists { not_exists: normalize_exists(not_exists) },
+        leaf @ (FilterExpr::Named(_) | FilterExpr::Predicate(_)) => leaf,
+    }
+}
+
+/// Canonical JSON of an (already normalized) expression; orders predicates by field first.
+fn filter_sort_key(expr: &FilterExpr) -> String {
+    serde_json::to_string(expr).unwrap_or_default()
+}
@@ -1,5 +1,11 @@
-use crate::dsl::report_spec::{FilterOp, Mode, ReportSpec};
-use crate::schema::field_catalog::{FieldType, WorkspaceSchema};
+use std::collections::{BTreeMap, HashSet};
+use crate::dsl::diagnostics::Diagnostic;
+use crate::dsl::report_spec::{
+    Aggregate, Filter, FilterExpr, FilterOp, HavingFilter, Mode, NamedFilter, RankOrder, RankSpec, ReportSpec,
+    SelectItem,
+};
+use crate::dsl::dates::RelativeDate;
+use crate::schema::field_catalog::{FieldDef, FieldType, NamedFilterDef, WorkspaceSchema};
 use serde_json::Value;
 use thiserror::Error;
 
@@ -9,7 +15,11 @@ pub enum SpecError {
     WorkspaceNotFound(String),
 
     #[error("unknown field '{field}' in {context}")]
-    UnknownField { field: String, context: &'static str },
+    UnknownField {
+        field: String,
+        context: &'static str,
+        suggestions: Vec<String>, // ranked, best first
+    },
 
     #[error("field '{field}' is not selectable")]
     NotSelectable { field: String },
@@ -17,6 +27,9 @@ pub enum SpecError {
     #[error("field '{field}' is not filterable")]
     NotFilterable { field: String },
 
+    #[error("field '{field}' is an aggregate and can only be filtered in having")]
+    AggregateInFilter { field: String },
+
     #[error("field '{field}' is not sortable")]
     NotSortable { field: String },
 
@@ -32,59 +45,544 @@ pub enum SpecError {
 
     #[error("export mode requires at least 1 select field")]
     ExportSelectEmpty,
+
+    #[error("'all'/'any' filter groups must not be empty")]
+    EmptyFilterGroup,
+
+    #[error("invalid aggregate '{agg:?}' for field '{field}' of type '{field_type:?}'")]
+    InvalidAggregate {
+        field: String,
+        agg: Aggregate,
+        field_type: FieldType,
+    },
+
+    #[error("field '{field}' is already an aggregate")]
+    AlreadyAggregated { field: String },
+
+    #[error("field '{field}' in {context} must be in group_by or aggregated")]
+    NotGrouped { field: String, context: &'static str },
+
+    #[error("having on field '{field}' must reference an aggregated select item")]
+    NotAggregated { field: String },
+
+    #[error("date_trunc on field '{field}' requires an unaggregated date field")]
+    InvalidDateTrunc { field: String },
+
+    #[error("invalid rank: {reason}")]
+    InvalidRank { reason: String },
+
+    #[error("unknown entity '{entity}' in exists")]
+    UnknownEntity { entity: String, suggestions: Vec<String> },
+
+    #[error("field '{field}' does not belong to exists entity '{entity}'")]
+    FieldOutsideExists { field: String, entity: String },
+
+    #[error("unknown named filter '{name}'")]
+    UnknownNamedFilter { name: String, suggestions: Vec<String> },
+
+    #[error("named filter '{name}' is declared on several entities: {}", entities.join(", "))]
+    AmbiguousNamedFilter { name: String, entities: Vec<String> },
+
+    #[error("invalid params for named filter '{name}': {reason}")]
+    InvalidFilterParams { name: String, reason: String },
+
+    #[error("named filter '{name}' does not belong to exists entity '{entity}'")]
+    NamedFilterOutsideExists { name: String, entity: String },
+
+    #[error("invalid args for field '{field}': {reason}")]
+    InvalidFieldArgs { field: String, reason: String },
+
+    #[error("field '{field}' needs a value for '{param}', which only filters can pass in args")]
+    ArgsRequired { field: String, param: String },
+}
+
+impl SpecError {
+    /// Stable identifier for diagnostics.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SpecError::WorkspaceNotFound(_) => "workspace_not_found",
+            SpecError::UnknownField { .. } => "unknown_field",
+            SpecError::NotSelectable { .. } => "not_selectable",
+            SpecError::NotFilterable { .. } => "not_filterable",
+            SpecError::AggregateInFilter { .. } => "aggregate_in_filter",
+            SpecError::NotSortable { .. } => "not_sortable",
+            SpecError::InvalidOperator { .. } => "invalid_operator",
+            SpecError::InvalidValue { .. } => "invalid_value",
+            SpecError::ExportSelectEmpty => "export_select_empty",
+            SpecError::EmptyFilterGroup => "empty_filter_group",
+            SpecError::InvalidAggregate { .. } => "invalid_aggregate",
+            SpecError::AlreadyAggregated { .. } => "already_aggregated",
+            SpecError::NotGrouped { .. } => "not_grouped",
+            SpecError::NotAggregated { .. } => "not_aggregated",
+            SpecError::InvalidDateTrunc { .. } => "invalid_date_trunc",
+            SpecError::InvalidRank { .. } => "invalid_rank",
+            SpecError::UnknownEntity { .. } => "unknown_entity",
+            SpecError::FieldOutsideExists { .. } => "field_outside_exists",
+            SpecError::UnknownNamedFilter { .. } => "unknown_named_filter",
+            SpecError::AmbiguousNamedFilter { .. } => "ambiguous_named_filter",
+            SpecError::InvalidFilterParams { .. } => "invalid_filter_params",
+            SpecError::NamedFilterOutsideExists { .. } => "named_filter_outside_exists",
+            SpecError::InvalidFieldArgs { .. } => "invalid_field_args",
+            SpecError::ArgsRequired { .. } => "args_required",
+        }
+    }
+
+    /// The member of a spec object (select item, filter, ...) the error is about.
+    fn member(&self) -> Option<&'static str> {
+        match self {
+            SpecError::UnknownField { .. }
+            | SpecError::NotSelectable { .. }
+            | SpecError::NotFilterable { .. }
+            | SpecError::AggregateInFilter { .. }
+            | SpecError::NotSortable { .. }
+            | SpecError::NotGrouped { .. }
+            | SpecError::NotAggregated { .. }
+            | SpecError::FieldOutsideExists { .. }
+            | SpecError::ArgsRequired { .. } => Some("field"),
+            SpecError::InvalidOperator { .. } => Some("op"),
+            SpecError::InvalidValue { .. } => Some("value"),
+            SpecError::InvalidAggregate { .. } | SpecError::AlreadyAggregated { .. } => Some("agg"),
+            SpecError::InvalidDateTrunc { .. } => Some("date_trunc"),
+            SpecError::UnknownEntity { .. } => Some("entity"),
+            SpecError::UnknownNamedFilter { .. }
+            | SpecError::AmbiguousNamedFilter { .. }
+            | SpecError::NamedFilterOutsideExists { .. } => Some("named"),
+            SpecError::InvalidFilterParams { .. } => Some("params"),
+            SpecError::InvalidFieldArgs { .. } => Some("args"),
+            _ => None,
+        }
+    }
+
+    pub fn suggestion(&self) -> Option<String> {
+        match self {
+            SpecError::UnknownField { suggestions, .. }
+            | SpecError::UnknownEntity { suggestions, .. }
+            | SpecError::UnknownNamedFilter { suggestions, .. } => did_you_mean(suggestions),
+            SpecError::ExportSelectEmpty => Some("select at least one field, or use preview mode".to_string()),
+            SpecError::NotGrouped { field, .. } => Some(format!("add '{}' to group_by or aggregate it", field)),
+            SpecError::AggregateInFilter { field } => Some(format!("select '{}' and filter on it in having", field)),
+            SpecError::NotAggregated { field } => {
+                Some(format!("select '{}' with the same agg and filter on that", field))
+            }
+            SpecError::FieldOutsideExists { entity, .. } => {
+                Some(format!("move the predicate out of the exists, or filter on a field of '{}'", entity))
+            }
+            SpecError::AmbiguousNamedFilter { entities, .. } => {
+                let quoted: Vec<String> = entities.iter().map(|e| format!("'{}'", e)).collect();
+                Some(format!("set 'entity' to one of {}", quoted.join(", ")))
+            }
+            SpecError::NamedFilterOutsideExists { entity, .. } => {
+                Some(format!("move the named filter out of the exists, or use one declared on '{}'", entity))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// "did you mean 'a', 'b' or 'c'?", or None without candidates.
+fn did_you_mean(suggestions: &[String]) -> Option<String> {
+    let quoted: Vec<String> = suggestions.iter().map(|s| format!("'{}'", s)).collect();
+    match quoted.as_slice() {
+        [] => None,
+        [only] => Some(format!("did you mean {}?", only)),
+        [init @ .., last] => Some(format!("did you mean {} or {}?", init.join(", "), last)),
+    }
+}
+
+/// Look up a field, or fail with the fields it most likely meant among those usable in
+/// `context`. Outside filters no args can be passed, so every param of the field must have
+/// a default.
+fn lookup<'a>(ws: &'a WorkspaceSchema, field: &str, context: &'static str) -> Result<&'a FieldDef, SpecError> {
+    let def = ws.fields.get(field).ok_or_else(|| SpecError::UnknownField {
+        field: field.to_string(),
+        context,
+        suggestions: ws.suggest_fields(field, |def| match context {
+            "filters" => def.filterable,
+            "order_by" => def.sortable,
+            "rank" => def.sortable || def.aggregate,
+            _ => def.selectable,
+        }),
+    })?;
+    match def.params.iter().find(|p| p.default.is_none()) {
+        Some(p) if context != "filters" => Err(SpecError::ArgsRequired { field: field.to_string(), param: p.name.clone() }),
+        _ => Ok(def),
+    }
+}
+
+/// Every error found in a spec, each with the JSON pointer of what it is about, in the
+/// order the checks run.
+#[derive(Debug, Default)]
+struct Findings(Vec<(String, SpecError)>);
+
+impl Findings {
+    /// Record the result of checking the spec object at `base`, pointing at its member.
+    fn at(&mut self, base: String, result: Result<(), SpecError>) {
+        if let Err(e) = result {
+            let pointer = match e.member() {
+                Some(member) => format!("{}/{}", base, member),
+                None => base,
+            };
+            self.0.push((pointer, e));
+        }
+    }
+
+    /// Record the result of checking a scalar (e.g. a group_by entry) at `pointer`.
+    fn exact(&mut self, pointer: String, result: Result<(), SpecError>) {
+        if let Err(e) = result {
+            self.0.push((pointer, e));
+        }
+    }
 }
 
+/// Validate a spec, returning the first error.
 pub fn validate_report_spec(spec: &ReportSpec, ws: Option<&WorkspaceSchema>) -> Result<(), SpecError> {
-    let ws = ws.ok_or_else(|| SpecError::WorkspaceNotFound(spec.workspace.clone()))?;
+    match collect_errors(spec, ws).0.into_iter().next() {
+        Some((_, e)) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Validate a spec, returning every error as a Diagnostic instead of stopping at the first.
+pub fn check_report_spec(spec: &ReportSpec, ws: Option<&WorkspaceSchema>) -> Vec<Diagnostic> {
+    collect_errors(spec, ws)
+        .0
+        .into_iter()
+        .map(|(pointer, e)| Diagnostic::error(e.code(), e.to_string(), pointer).with_suggestion(e.suggestion()))
+        .collect()
+}
+
+fn collect_errors(spec: &ReportSpec, ws: Option<&WorkspaceSchema>) -> Findings {
+    let mut found = Findings::default();
+    let Some(ws) = ws else {
+        found.exact("/workspace".to_string(), Err(SpecError::WorkspaceNotFound(spec.workspace.clone())));
+        return found;
+    };
 
     if matches!(spec.mode, Mode::Export) && spec.select.is_empty() {
-        return Err(SpecError::ExportSelectEmpty);
+        found.exact("/select".to_string(), Err(SpecError::ExportSelectEmpty));
     }
 
-    // Validate select fields
-    for sel in &spec.select {
-        let def = ws
-            .fields
-            .get(&sel.field)
-            .ok_or_else(|| SpecError::UnknownField {
-                field: sel.field.clone(),
-                context: "select",
-            })?;
+    for (i, sel) in spec.select.iter().enumerate() {
+        found.at(format!("/select/{}", i), validate_select_item(sel, ws));
+    }
+    for (i, field) in spec.group_by.iter().enumerate() {
+        found.exact(format!("/group_by/{}", i), validate_group_by_field(field, ws));
+    }
+    for (i, f) in spec.filters.iter().enumerate() {
+        validate_filter_expr(f, ws, format!("/filters/{}", i), &mut found);
+    }
+    for (i, ob) in spec.order_by.iter().enumerate() {
+        found.at(format!("/order_by/{}", i), validate_order_by(&ob.field, ws));
+    }
+    for (i, h) in spec.having.iter().enumerate() {
+        found.at(format!("/having/{}", i), validate_having(h, spec, ws));
+    }
+    if let Some(rank) = &spec.rank {
+        validate_rank(rank, spec, ws, &mut found);
+    }
+
+    validate_grouping(spec, ws, &mut found);
+    found
+}
+
+fn validate_select_item(sel: &SelectItem, ws: &WorkspaceSchema) -> Result<(), SpecError> {
+    let def = lookup(ws, &sel.field, "select")?;
+
+    if !def.selectable {
+        return Err(SpecError::NotSelectable { field: sel.field.clone() });
+    }
 
-        if !def.selectable {
-            return Err(SpecError::NotSelectable {
-                field: sel.field.clone(),
-            });
+    if let Some(agg) = sel.agg {
+        validate_aggregate(&sel.field, def, agg)?;
+    }
+
+    if sel.date_trunc.is_some() && (sel.agg.is_some() || def.field_type != FieldType::Date) {
+        return Err(SpecError::InvalidDateTrunc { field: sel.field.clone() });
+    }
+    Ok(())
+}
+
+fn validate_group_by_field(field: &str, ws: &WorkspaceSchema) -> Result<(), SpecError> {
+    let def = lookup(ws, field, "group_by")?;
+
+    if !def.selectable {
+        return Err(SpecError::NotSelectable { field: field.to_string() });
+    }
+    if def.aggregate {
+        return Err(SpecError::AlreadyAggregated { field: field.to_string() });
+    }
+    Ok(())
+}
+
+fn validate_order_by(field: &str, ws: &WorkspaceSchema) -> Result<(), SpecError> {
+    let def = lookup(ws, field, "order_by")?;
+
+    if !def.sortable {
+        return Err(SpecError::NotSortable { field: field.to_string() });
+    }
+    Ok(())
+}
+
+fn validate_having(h: &HavingFilter, spec: &ReportSpec, ws: &WorkspaceSchema) -> Result<(), SpecError> {
+    let def = lookup(ws, &h.field, "having")?;
+
+    let aggregated = h.agg.is_some() || def.aggregate;
+    let selected = spec.select.iter().any(|s| s.field == h.field && s.agg == h.agg);
+    if !aggregated || !selected {
+        return Err(SpecError::NotAggregated { field: h.field.clone() });
+    }
+
+    let ty = h.agg.map_or(def.field_type, |agg| agg.result_type(def.field_type));
+    validate_filter_op(&h.field, ty, h.op)?;
+    validate_filter_value(&h.field, ty, &h.value, h.op)
+}
+
+fn validate_aggregate(field: &str, def: &FieldDef, agg: Aggregate) -> Result<(), SpecError> {
+    use Aggregate::*;
+    use FieldType::*;
+
+    if def.aggregate {
+        return Err(SpecError::AlreadyAggregated { field: field.to_string() });
+    }
+
+    let ok = matches!(
+        (def.field_type, agg),
+        (_, Count | CountDistinct)
+            | (Number, Sum | Avg)
+            | (String | Number | Date | Enum, Min | Max)
+            | (String | Enum, StringAgg)
+    );
+
+    if ok {
+        Ok(())
+    } else {
+        Err(SpecError::InvalidAggregate {
+            field: field.to_string(),
+            agg,
+            field_type: def.field_type,
+        })
+    }
+}
+
+fn validate_rank_order(ro: &RankOrder, ws: &WorkspaceSchema) -> Result<(), SpecError> {
+    let def = lookup(ws, &ro.field, "rank")?;
+    match ro.agg {
+        Some(agg) => validate_aggregate(&ro.field, def, agg),
+        None if !def.sortable && !def.aggregate => Err(SpecError::NotSortable { field: ro.field.clone() }),
+        None => Ok(()),
+    }
+}
+
+fn validate_rank(rank: &RankSpec, spec: &ReportSpec, ws: &WorkspaceSchema, found: &mut Findings) {
+    let invalid = |reason: String| Err(SpecError::InvalidRank { reason });
+
+    for (i, field) in rank.partition_by.iter().enumerate() {
+        let result = match lookup(ws, field, "rank") {
+            Ok(def) if def.aggregate => Err(SpecError::AlreadyAggregated { field: field.clone() }),
+            other => other.map(|_| ()),
+        };
+        found.exact(format!("/rank/partition_by/{}", i), result);
+    }
+
+    if rank.order_by.is_empty() {
+        found.exact("/rank/order_by".to_string(), invalid("order_by must not be empty".to_string()));
+    }
+    for (i, ro) in rank.order_by.iter().enumerate() {
+        found.at(format!("/rank/order_by/{}", i), validate_rank_order(ro, ws));
+    }
+
+    if rank.top_n == Some(0) {
+        found.exact("/rank/top_n".to_string(), invalid("top_n must be at least 1".to_string()));
+    }
+
+    // The ranked rows are read back from a CTE by output column name.
+    let mut names = HashSet::new();
+    let duplicate = spec
+        .select
+        .iter()
+        .map(|s| s.alias.as_deref().unwrap_or(&s.field))
+        .chain([rank.alias.as_str()])
+        .find(|name| !names.insert(*name));
+    if let Some(name) = duplicate {
+        found.exact("/rank/alias".to_string(), invalid(format!("output column '{}' is not unique", name)));
+    }
+    for (i, ob) in spec.order_by.iter().enumerate() {
+        if !spec.select.iter().any(|s| s.field == ob.field) {
+            found.exact(
+                format!("/order_by/{}/field", i),
+                invalid(format!("order_by field '{}' must be selected", ob.field)),
+            );
         }
     }
+}
 
-    // Validate filters
-    for f in &spec.filters {
-        let def = ws.fields.get(&f.field).ok_or_else(|| SpecError::UnknownField {
-            field: f.field.clone(),
-            context: "filters",
-        })?;
+/// When the report aggregates, every plain select, rank and order_by field must be grouped.
+/// An empty group_by groups by the plain select fields, so select always passes then.
+fn validate_grouping(spec: &ReportSpec, ws: &WorkspaceSchema, found: &mut Findings) {
+    let is_aggregate = |field: &str| ws.fields.get(field).is_some_and(|d| d.aggregate);
+    let is_plain = |s: &SelectItem| s.agg.is_none() && !is_aggregate(&s.field);
 
-        if !def.filterable {
-            return Err(SpecError::NotFilterable { field: f.field.clone() });
+    let aggregates = spec.select.iter().any(|s| s.agg.is_some() || is_aggregate(&s.field));
+    if !aggregates && spec.group_by.is_empty() {
+        return;
+    }
+
+    let grouped: HashSet<&str> = if spec.group_by.is_empty() {
+        spec.select.iter().filter(|s| is_plain(s)).map(|s| s.field.as_str()).collect()
+    } else {
+        spec.group_by.iter().map(String::as_str).collect()
+    };
+    let check = |field: &str, context: &'static str| {
+        if grouped.contains(field) || is_aggregate(field) {
+            Ok(())
+        } else {
+            Err(SpecError::NotGrouped { field: field.to_string(), context })
         }
+    };
 
-        validate_filter_op(&f.field, def.field_type, f.op)?;
-        validate_filter_value(&f.field, def.field_type, &f.value, f.op)?;
+    for (i, sel) in spec.select.iter().enumerate().filter(|(_, s)| is_plain(s)) {
+        found.at(format!("/select/{}", i), check(&sel.field, "select"));
     }
 
-    // Validate order_by
-    for ob in &spec.order_by {
-        let def = ws.fields.get(&ob.field).ok_or_else(|| SpecError::UnknownField {
-            field: ob.field.clone(),
-            context: "order_by",
-        })?;
+    if let Some(rank) = &spec.rank {
+        for (i, field) in rank.partition_by.iter().enumerate() {
+            found.exact(format!("/rank/partition_by/{}", i), check(field, "rank"));
+        }
+        for (i, ro) in rank.order_by.iter().enumerate().filter(|(_, ro)| ro.agg.is_none()) {
+            found.at(format!("/rank/order_by/{}", i), check(&ro.field, "rank"));
+        }
+        // order_by sorts the ranked output columns, which are grouped or aggregated already
+        return;
+    }
+
+    for (i, ob) in spec.order_by.iter().enumerate() {
+        found.at(format!("/order_by/{}", i), check(&ob.field, "order_by"));
+    }
+}
 
-        if !def.sortable {
-            return Err(SpecError::NotSortable { field: ob.field.clone() });
+fn validate_filter_expr(expr: &FilterExpr, ws: &WorkspaceSchema, pointer: String, found: &mut Findings) {
+    match expr {
+        FilterExpr::All { all: children } | FilterExpr::Any { any: children } => {
+            let key = if matches!(expr, FilterExpr::All { .. }) { "all" } else { "any" };
+            if children.is_empty() {
+                found.exact(format!("{}/{}", pointer, key), Err(SpecError::EmptyFilterGroup));
+            }
+            for (i, c) in children.iter().enumerate() {
+                validate_filter_expr(c, ws, format!("{}/{}/{}", pointer, key, i), found);
+            }
         }
+        FilterExpr::Not { not } => validate_filter_expr(not, ws, format!("{}/not", pointer), found),
+        FilterExpr::Exists { exists: e } | FilterExpr::NotExists { not_exists: e } => {
+            let key = if matches!(expr, FilterExpr::Exists { .. }) { "exists" } else { "not_exists" };
+            let base = format!("{}/{}", pointer, key);
+            if !ws.entities.contains(&e.entity) {
+                found.at(base.clone(), Err(SpecError::UnknownEntity {
+                    entity: e.entity.clone(),
+                    suggestions: ws.suggest_entities(&e.entity),
+                }));
+            }
+
+            for (i, c) in e.filters.iter().enumerate() {
+                let child = format!("{}/filters/{}", base, i);
+                validate_filter_expr(c, ws, child.clone(), found);
+
+                let outside = c
+                    .predicates()
+                    .into_iter()
+                    .find(|f| ws.fields.get(&f.field).is_some_and(|d| d.entity != e.entity));
+                let outside_named = c
+                    .named_filters()
+                    .into_iter()
+                    .find(|n| resolve_named_filter(n, ws).is_ok_and(|def| def.entity != e.entity));
+                let err = match (outside, outside_named) {
+                    (Some(f), _) => SpecError::FieldOutsideExists { field: f.field.clone(), entity: e.entity.clone() },
+                    (None, Some(n)) => SpecError::NamedFilterOutsideExists { name: n.named.clone(), entity: e.entity.clone() },
+                    (None, None) => continue,
+                };
+                if matches!(c, FilterExpr::Predicate(_) | FilterExpr::Named(_)) {
+                    found.at(child, Err(err));
+                } else {
+                    found.exact(child, Err(err));
+                }
+            }
+        }
+        FilterExpr::Named(n) => found.at(pointer, validate_named_filter(n, ws)),
+        FilterExpr::Predicate(f) => found.at(pointer, validate_predicate(f, ws)),
+    }
+}
+
+/// The common filter a named filter refers to: the only one with its name (on its
+/// `entity`, when given).
+fn resolve_named_filter<'a>(n: &NamedFilter, ws: &'a WorkspaceSchema) -> Result<&'a NamedFilterDef, SpecError> {
+    let candidates: Vec<&NamedFilterDef> = ws
+        .named_filters
+        .iter()
+        .filter(|f| f.name == n.named && n.entity.as_ref().is_none_or(|e| *e == f.entity))
+        .collect();
+    match candidates.as_slice() {
+        [] => Err(SpecError::UnknownNamedFilter {
+            name: n.named.clone(),
+            suggestions: ws.suggest_named_filters(&n.named),
+        }),
+        [def] => Ok(def),
+        _ => Err(SpecError::AmbiguousNamedFilter {
+            name: n.named.clone(),
+            entities: candidates.iter().map(|f| f.entity.clone()).collect(),
+        }),
     }
+}
+
+fn validate_named_filter(n: &NamedFilter, ws: &WorkspaceSchema) -> Result<(), SpecError> {
+    let def = resolve_named_filter(n, ws)?;
+    let invalid = |reason: String| SpecError::InvalidFilterParams { name: n.named.clone(), reason };
+
+    if let Some(missing) = def.params.iter().find(|p| !n.params.contains_key(&p.name)) {
+        return Err(invalid(format!("missing '{}'", missing.name)));
+    }
+    if let Some(extra) = n.params.keys().find(|k| !def.params.iter().any(|p| &p.name == *k)) {
+        return Err(invalid(format!("unknown param '{}'", extra)));
+    }
+
+    for p in &def.params {
+        if !p.param_type.accepts(&n.params[&p.name]) {
+            return Err(invalid(format!("'{}' must be {}", p.name, p.param_type.expected())));
+        }
+    }
+    Ok(())
+}
+
+fn validate_predicate(f: &Filter, ws: &WorkspaceSchema) -> Result<(), SpecError> {
+    let def = lookup(ws, &f.field, "filters")?;
+
+    if def.aggregate {
+        return Err(SpecError::AggregateInFilter { field: f.field.clone() });
+    }
+    if !def.filterable {
+        return Err(SpecError::NotFilterable { field: f.field.clone() });
+    }
+
+    validate_field_args(&f.field, def, &f.args)?;
+    validate_filter_op(&f.field, def.field_type, f.op)?;
+    validate_filter_value(&f.field, def.field_type, &f.value, f.op)
+}
+
+/// Check a predicate's `args` against the params of its derived field: each is known and of
+/// the param's type, and every param without a default is given.
+fn validate_field_args(field: &str, def: &FieldDef, args: &BTreeMap<String, Value>) -> Result<(), SpecError> {
+    let invalid = |reason: String| Err(SpecError::InvalidFieldArgs { field: field.to_string(), reason });
 
+    if let Some(extra) = args.keys().find(|k| !def.params.iter().any(|p| &p.name == *k)) {
+        return invalid(format!("unknown arg '{}'", extra));
+    }
+    for p in &def.params {
+        match args.get(&p.name) {
+            Some(v) if !p.param_type.accepts(v) => {
+                return invalid(format!("'{}' must be {}", p.name, p.param_type.expected()));
+            }
+            None if p.default.is_none() => return invalid(format!("missing '{}'", p.name)),
+            _ => {}
+        }
+    }
     Ok(())
 }
 
@@ -93,17 +591,26 @@ fn validate_filter_op(field: &str, ty: FieldType, op: FilterOp) -> Result<(), Sp
     use FilterOp::*;
 
     let ok = match (ty, op) {
-        // eq works for most scalars
-        (String | Number | Date | Enum | Bool, Eq) => true,
+        // eq/neq work for most scalars
+        (String | Number | Date | Enum | Bool, Eq | Neq) => true,
 
-        // in works for scalar types (value must be array)
-        (String | Number | Date | Enum, In) => true,
+        // in/not_in work for scalar types (value must be array)
+        (String | Number | Date | Enum, In | NotIn) => true,
 
-        // overlaps only for arrays
-        (StringArray, Overlaps) => true,
+        // overlaps/contains only for arrays
+        (StringArray, Overlaps | Contains) => true,
 
         // comparisons for dates/numbers
-        (Date | Number, Gte | Lte) => true,
+        (Date | Number, Gt | Gte | Lt | Lte | Between) => true,
+
+        // null checks for everything
+        (_, IsNull | IsNotNull) => true,
+
+        // relative dates ("last_30_days", "this_quarter", ...)
+        (Date, Relative) => true,
+
+        // pattern matching for text
+        (String | Enum, StartsWith | ILike) => true,
 
         _ => false,
     };
@@ -119,42 +626,65 @@ fn validate_filter_op(field: &str, ty: FieldType, op: FilterOp) -> Result<(), Sp
     }
 }
 
-fn validate_filter_value(field: &str, ty: FieldType, v: &Value, op: FilterOp) -> Result<(), SpecError> {
+fn validate_scalar(field: &str, ty: FieldType, v: &Value) -> Result<(), SpecError> {
     use FieldType::*;
+
+    let (ok, expected) = match ty {
+        String | Enum => (v.is_string(), "expected string"),
+        Bool => (v.is_boolean(), "expected boolean"),
+        Number => (v.is_number(), "expected number"),
+        Date => (v.is_string(), "expected date string"),
+        StringArray => (v.is_string(), "expected string element"),
+    };
+
+    if ok {
+        Ok(())
+    } else {
+        Err(SpecError::InvalidValue { field: field.to_string(), reason: expected.to_string() })
+    }
+}
+
+fn validate_filter_value(field: &str, ty: FieldType, v: &Value, op: FilterOp) -> Result<(), SpecError> {
     use FilterOp::*;
 
     let err = |reason: &str| Err(SpecError::InvalidValue { field: field.to_string(), reason: reason.to_string() });
+    let op_name = serde_json::to_value(op).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default();
 
-    match (ty, op) {
-        (String | Enum, Eq) => v.as_str().map(|_| ()).ok_or_else(|| SpecError::InvalidValue { field: field.to_string(), reason: "expected string".to_string() })?,
-        (Bool, Eq) => v.as_bool().map(|_| ()).ok_or_else(|| SpecError::InvalidValue { field: field.to_string(), reason: "expected boolean".to_string() })?,
-        (Number, Eq) => v.as_f64().map(|_| ()).ok_or_else(|| SpecError::InvalidValue { field: field.to_string(), reason: "expected number".to_string() })?,
-        (Date, Eq) => v.as_str().map(|_| ()).ok_or_else(|| SpecError::InvalidValue { field: field.to_string(), reason: "expected date string".to_string() })?,
+    match op {
+        Eq | Neq | Gt | Gte | Lt | Lte => validate_scalar(field, ty, v)?,
 
-        (String | Enum | Number | Date, In) => {
-            let arr = v.as_array().ok_or_else(|| SpecError::InvalidValue { field: field.to_string(), reason: "expected array for 'in'".to_string() })?;
+        In | NotIn | Overlaps | Contains => {
+            let arr = v.as_array().ok_or_else(|| SpecError::InvalidValue { field: field.to_string(), reason: format!("expected array for '{}'", op_name) })?;
             if arr.is_empty() {
-                return err("array for 'in' must not be empty");
+                return err(&format!("array for '{}' must not be empty", op_name));
             }
+            arr.iter().try_for_each(|e| validate_scalar(field, ty, e))?;
         }
 
-        (StringArray, Overlaps) => {
-            let arr = v.as_array().ok_or_else(|| SpecError::InvalidValue { field: field.to_string(), reason: "expected array for 'overlaps'".to_string() })?;
-            if arr.is_empty() {
-                return err("array for 'overlaps' must not be empty");
+        Between => match v.as_array().map(Vec::as_slice) {
+            Some([low, high]) => {
+                validate_scalar(field, ty, low)?;
+                validate_scalar(field, ty, high)?;
+            }
+            _ => return err("expected [low, high] for 'between'"),
+        },
+
+        IsNull | IsNotNull => {
+            if !v.is_null() {
+                return err(&format!("'{}' takes no value", op_name));
             }
         }
 
-        (Number | Date, Gte | Lte) => {
-            // simplest: accept string for Date and number for Number
-            match ty {
-                Number => { v.as_f64().ok_or_else(|| SpecError::InvalidValue { field: field.to_string(), reason: "expected number".to_string() })?; }
-                Date => { v.as_str().ok_or_else(|| SpecError::InvalidValue { field: field.to_string(), reason: "expected date string".to_string() })?; }
-                _ => {}
+        Relative => {
+            if v.as_str().and_then(RelativeDate::parse).is_none() {
+                return err("expected a relative date such as 'last_30_days', 'this_quarter' or 'before_now'");
             }
         }
 
-        _ => {} // op/type mismatch already caught
+        StartsWith | ILike => match v.as_str() {
+            Some(s) if !s.is_empty() => {}
+            _ => return err(&format!("expected non-empty string for '{}'", op_name)),
+        },
     }
 
     Ok(())
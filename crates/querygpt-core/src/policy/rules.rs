@@ -1,2 +1,30 @@
+use anyhow::anyhow;
+use crate::dsl::plan::IntermediatePlan;
+
 /// Stub: policy checks (profile isolation, allowed tables, disallow writes).
 pub fn enforce_read_only(_sql: &str) -> anyhow::Result<()> { Ok(()) }
+
+/// Refuse plans that are not isolated to at least one profile on their root table.
+/// Joins carry `profile` to every other table (see `dsl::join_rules`), so the root
+/// predicate is enough to keep other tenants' rows out.
+pub fn enforce_profile_isolation(plan: &IntermediatePlan) -> anyhow::Result<()> {
+    let scope = plan
+        .profile_scope
+        .as_ref()
+        .ok_or_else(|| anyhow!("plan for {} has no profile isolation predicate", plan.workspace))?;
+
+    if scope.profiles.is_empty() {
+        return Err(anyhow!("plan for {} is isolated to an empty profile set", plan.workspace));
+    }
+
+    let root = plan.root_alias()?;
+    if scope.alias != root {
+        return Err(anyhow!(
+            "profile isolation applies to '{}' but the plan is rooted at '{}'",
+            scope.alias,
+            root
+        ));
+    }
+
+    Ok(())
+}
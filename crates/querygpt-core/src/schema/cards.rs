@@ -1,4 +1,8 @@
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use sqlparser::ast::{Expr, UnaryOperator, Value};
+use crate::schema::field_catalog::FieldType;
+use crate::sql::expr::{function_args, parse_expr, visit_exprs_mut};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SchemaCards {
@@ -9,6 +13,209 @@ pub struct SchemaCards {
     pub join_graph: JoinGraph,
     pub derived_fields: Vec<DerivedField>,
     pub conventions: Conventions,
+    #[serde(default)]
+    pub field_catalog: Vec<FieldCard>,
+}
+
+impl SchemaCards {
+    pub fn field(&self, name: &str) -> Option<&FieldCard> {
+        self.field_catalog.iter().find(|f| f.name == name)
+    }
+
+    pub fn derived_field(&self, name: &str) -> Option<&DerivedField> {
+        self.derived_fields.iter().find(|df| df.name == name)
+    }
+
+    /// The entity a join graph node reads: the node itself, or the target of the edge that
+    /// introduces it as a role.
+    pub fn node_entity<'a>(&'a self, node: &'a str) -> &'a str {
+        self.join_graph
+            .edges
+            .iter()
+            .find(|e| e.role.as_deref() == Some(node))
+            .map_or(node, |e| e.to.as_str())
+    }
+
+    pub fn column(&self, entity: &str, column: &str) -> Option<&ColumnCard> {
+        self.entities
+            .iter()
+            .find(|e| e.name == entity)
+            .and_then(|e| e.columns.iter().find(|c| c.name == column))
+    }
+
+    /// The common filters named `name`, on `entity` when given, with the entity declaring each.
+    pub fn common_filters<'a>(&'a self, name: &'a str, entity: Option<&'a str>) -> impl Iterator<Item = (&'a EntityCard, &'a FilterHint)> {
+        self.entities
+            .iter()
+            .filter(move |e| entity.is_none_or(|entity| e.name == entity))
+            .flat_map(move |e| e.common_filters.iter().filter(move |f| f.name == name).map(move |f| (e, f)))
+    }
+
+    /// Check every entity's common filters: names are unique per entity and the SQL parses
+    /// and only reads the entity's own columns.
+    pub fn check_common_filters(&self) -> anyhow::Result<()> {
+        for entity in &self.entities {
+            for (i, hint) in entity.common_filters.iter().enumerate() {
+                if entity.common_filters[..i].iter().any(|h| h.name == hint.name) {
+                    anyhow::bail!("common filter {} is declared twice on {}", hint.name, entity.name);
+                }
+                hint.parse(entity)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check every derived field: the SQL parses and reads only what it declares, each
+    /// dependency exists (a derived field read through an aggregate is an aggregate too),
+    /// each read of a derived field passes valid arguments, catalog entries agree on the type
+    /// (and leave aggregates unfilterable, since WHERE cannot read them), and no derived
+    /// fields depend on each other in a cycle.
+    pub fn check_derived_fields(&self) -> anyhow::Result<()> {
+        for df in &self.derived_fields {
+            let mut expr = df.parse()?;
+            for dependency in &df.depends_on {
+                match dependency.split_once('.') {
+                    Some((entity, column)) => {
+                        if self.column(entity, column).is_none() {
+                            anyhow::bail!("derived field {} depends on unknown column {}", df.name, dependency);
+                        }
+                    }
+                    None => match self.derived_field(dependency) {
+                        None => anyhow::bail!("derived field {} depends on unknown derived field {}", df.name, dependency),
+                        Some(dep) if dep.aggregate && !df.aggregate => anyhow::bail!(
+                            "derived field {} reads aggregate {} so must be an aggregate itself",
+                            df.name,
+                            dependency
+                        ),
+                        Some(_) => {}
+                    },
+                }
+            }
+
+            for p in &df.params {
+                if p.default.as_ref().is_some_and(|d| !p.param_type.accepts(d)) {
+                    anyhow::bail!("derived field {}: the default of {} must be {}", df.name, p.name, p.param_type.expected());
+                }
+            }
+            visit_exprs_mut(&mut expr, &mut |e| {
+                let (name, args) = match e {
+                    Expr::Identifier(name) => (name.value.clone(), vec![]),
+                    Expr::Function(f) if self.derived_field(&f.name.to_string()).is_some() => {
+                        let name = f.name.to_string();
+                        if !df.derived_dependencies().any(|d| d == name) {
+                            anyhow::bail!("reads {} without listing it in depends_on", name);
+                        }
+                        (name, function_args(f)?)
+                    }
+                    _ => return Ok(()),
+                };
+                self.derived_field(&name).map_or(Ok(()), |dep| dep.check_call(df, &args))
+            })
+            .with_context(|| format!("derived field {}", df.name))?;
+
+            if let Some(card) = self.field(&df.name) {
+                if card.field_type != df.result_type {
+                    anyhow::bail!(
+                        "field {} is {:?} in the catalog but its derived field returns {:?}",
+                        df.name,
+                        card.field_type,
+                        df.result_type
+                    );
+                }
+                if df.aggregate && card.filterable {
+                    anyhow::bail!("field {} aggregates rows, so it can only be filtered in having, not marked filterable", df.name);
+                }
+            }
+        }
+
+        let mut done = Vec::new();
+        for df in &self.derived_fields {
+            self.order_derived(df, &mut Vec::new(), &mut done)?;
+        }
+        Ok(())
+    }
+
+    /// Append `df` to `done` after the derived fields it reads, depth first; `stack` holds the
+    /// fields being visited, so a cycle is reported instead of recursing forever.
+    fn order_derived<'a>(
+        &'a self,
+        df: &'a DerivedField,
+        stack: &mut Vec<&'a str>,
+        done: &mut Vec<&'a str>,
+    ) -> anyhow::Result<()> {
+        if done.contains(&df.name.as_str()) {
+            return Ok(());
+        }
+        if let Some(i) = stack.iter().position(|n| *n == df.name) {
+            anyhow::bail!("derived fields form a cycle: {} -> {}", stack[i..].join(" -> "), df.name);
+        }
+        stack.push(&df.name);
+        for dep in df.derived_dependencies().filter_map(|d| self.derived_field(d)) {
+            self.order_derived(dep, stack, done)?;
+        }
+        stack.pop();
+        done.push(&df.name);
+        Ok(())
+    }
+
+    /// Add a catalog field for every entity `json_paths` entry that no catalog field reads
+    /// yet, so declared paths are selectable, filterable and (unless arrays) sortable without
+    /// a hand-written FieldCard. A hand-written FieldCard for the same column and path wins,
+    /// e.g. to rename it or narrow its flags. Safe to call more than once.
+    pub fn expand_json_paths(&mut self) -> anyhow::Result<()> {
+        let mut generated = Vec::new();
+        for entity in &self.entities {
+            for jp in &entity.json_paths {
+                let declared = self.field_catalog.iter().any(|f| {
+                    f.entity == entity.name
+                        && f.column.as_deref() == Some(jp.column.as_str())
+                        && f.json_path.as_deref() == Some(jp.path.as_str())
+                });
+                if declared {
+                    continue;
+                }
+                let field_type = jp.field_type().ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "json path {} on {}.{} has unsupported data_type '{}'",
+                        jp.path,
+                        entity.name,
+                        jp.column,
+                        jp.data_type
+                    )
+                })?;
+                let name = jp.field_name();
+                if self.field(&name).is_some() || generated.iter().any(|f: &FieldCard| f.name == name) {
+                    anyhow::bail!(
+                        "json path {} on {}.{} would be field '{}', which already exists; set its `field`",
+                        jp.path,
+                        entity.name,
+                        jp.column,
+                        name
+                    );
+                }
+                generated.push(FieldCard {
+                    name,
+                    entity: entity.name.clone(),
+                    column: Some(jp.column.clone()),
+                    json_path: Some(jp.path.clone()),
+                    field_type,
+                    selectable: true,
+                    filterable: true,
+                    sortable: field_type != FieldType::StringArray,
+                    role: None,
+                    synonyms: Vec::new(),
+                });
+            }
+        }
+        self.field_catalog.extend(generated);
+        Ok(())
+    }
+
+    /// True if the catalog field is a derived field that aggregates rows (e.g. STRING_AGG).
+    pub fn is_aggregate_field(&self, name: &str) -> bool {
+        self.field(name).is_some_and(|f| f.column.is_none())
+            && self.derived_field(name).is_some_and(|df| df.aggregate)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,9 +227,12 @@ pub struct Conventions {
     pub notes: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct EntityCard {
     pub name: String,
+    /// Preferred table alias, e.g. `o`; see `dsl::aliases` for how collisions are resolved.
+    #[serde(default)]
+    pub alias: Option<String>,
     pub kind: EntityKind,
     pub description: String,
     pub primary_key: Vec<String>,
@@ -32,9 +242,10 @@ pub struct EntityCard {
     pub tags: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum EntityKind {
+    #[default]
     Table,
     MaterializedView,
     View,
@@ -53,15 +264,107 @@ pub struct ColumnCard {
 pub struct JsonPathCard {
     pub column: String,
     pub path: String,
-    pub data_type: String,
+    pub data_type: String, // "string" | "number" | "boolean" | "date" | "string_array" | "enum"
     pub description: String,
+    /// Catalog field name; defaults to the snake_cased path (`$.packageId` -> `package_id`).
+    #[serde(default)]
+    pub field: Option<String>,
+}
+
+impl JsonPathCard {
+    pub fn field_name(&self) -> String {
+        if let Some(name) = &self.field {
+            return name.clone();
+        }
+        let mut name = String::new();
+        for c in self.path.trim_start_matches("$.").chars() {
+            match c {
+                '.' => name.push('_'),
+                c if c.is_ascii_uppercase() => {
+                    if !name.is_empty() && !name.ends_with('_') {
+                        name.push('_');
+                    }
+                    name.push(c.to_ascii_lowercase());
+                }
+                c => name.push(c),
+            }
+        }
+        name
+    }
+
+    pub fn field_type(&self) -> Option<FieldType> {
+        Some(match self.data_type.as_str() {
+            "string" | "text" => FieldType::String,
+            "number" | "integer" | "numeric" => FieldType::Number,
+            "boolean" => FieldType::Bool,
+            "date" | "timestamp" => FieldType::Date,
+            "string_array" | "array" => FieldType::StringArray,
+            "enum" => FieldType::Enum,
+            _ => return None,
+        })
+    }
 }
 
+/// A reusable filter on one entity, used by ReportSpec `{"named": ...}` filters.
+/// `sql` reads columns of the entity, bare or qualified by the entity name, and may take
+/// parameters as `:name` slots, e.g. `countries && :countries`, each declared in `params`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FilterHint {
     pub name: String,
     pub sql: String,
     pub description: String,
+    #[serde(default)]
+    pub params: Vec<FilterParam>,
+}
+
+/// A `:name` slot of a FilterHint. Values are checked against `param_type` and bound as it,
+/// e.g. a `string_array` as `ARRAY[$2, $3]` of text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterParam {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub param_type: FieldType,
+}
+
+impl FilterHint {
+    /// Parse `sql`, checking that it only reads columns of `entity` and that its `:name`
+    /// slots are exactly the declared `params`.
+    pub fn parse(&self, entity: &EntityCard) -> anyhow::Result<Expr> {
+        let mut expr = parse_expr(&self.sql)?;
+        let mut slots = Vec::new();
+        visit_exprs_mut(&mut expr, &mut |e| {
+            let column = match e {
+                Expr::Identifier(column) => column,
+                Expr::CompoundIdentifier(parts) => match parts.as_slice() {
+                    [table, column] if table.value == entity.name => column,
+                    _ => anyhow::bail!("'{}' is not a column of {}", e, entity.name),
+                },
+                Expr::Value(v) => {
+                    if let Value::Placeholder(p) = &v.value {
+                        let slot = p
+                            .strip_prefix(':')
+                            .ok_or_else(|| anyhow::anyhow!("parameter '{}' must be written :name", p))?;
+                        if !self.params.iter().any(|d| d.name == slot) {
+                            anyhow::bail!("parameter '{}' is not declared in params", p);
+                        }
+                        slots.push(slot.to_string());
+                    }
+                    return Ok(());
+                }
+                _ => return Ok(()),
+            };
+            if !entity.columns.iter().any(|c| c.name == column.value) {
+                anyhow::bail!("'{}' is not a column of {}", column.value, entity.name);
+            }
+            Ok(())
+        })
+        .and_then(|()| match self.params.iter().find(|d| !slots.contains(&d.name)) {
+            Some(unused) => anyhow::bail!("param {} is never used as :{}", unused.name, unused.name),
+            None => Ok(()),
+        })
+        .with_context(|| format!("common filter {} on {}", self.name, entity.name))?;
+        Ok(expr)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -78,15 +381,205 @@ pub struct JoinEdge {
     pub on: Vec<String>,   // list of equality predicates as strings
     pub cardinality: String, // "1:1" | "1:n" | "n:1" | "n:n"
     pub safe: bool,
+    #[serde(default)]
+    pub version_rule: VersionRule,
     pub notes: Vec<String>,
+    /// Joins `to` as a separate table instance named by the role, so an entity can appear
+    /// twice (e.g. a self-join to a parent row). `on` refers to that instance by the role.
+    #[serde(default)]
+    pub role: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl JoinEdge {
+    /// The join graph node this edge leads to: its role, or `to`.
+    pub fn to_node(&self) -> &str {
+        self.role.as_deref().unwrap_or(&self.to)
+    }
+}
+
+/// How an edge treats `Conventions.version_column`.
+/// - `match`: both sides are version-aligned and the join must equate their versions
+/// - `forbid`: versions are independent and must never be equated
+///   (e.g. campaign_offers.version tracks the CAMPAIGN version, not the offer version)
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionRule {
+    Match,
+    Forbid,
+    #[default]
+    Unchecked,
+}
+
+/// A field computed by SQL. `sql` reads columns as `entity.column`, other derived fields
+/// by name (`expired_or_live_status`, or with arguments: `expires_within(7)`) and its own
+/// `params` as `:name`. Every column and derived field it reads is listed in `depends_on`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DerivedField {
     pub name: String,
     pub sql: String,
     pub description: String,
     pub depends_on: Vec<String>,
+    #[serde(default)]
+    pub aggregate: bool,
+    /// Type of the value `sql` returns; the field's catalog entry must have the same type.
+    pub result_type: FieldType,
+    #[serde(default)]
+    pub params: Vec<DerivedParam>,
+}
+
+/// A typed input of a derived field. A ReportSpec filter passes it by name in `args`, and
+/// another derived field positionally; `default` is used when neither does.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DerivedParam {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub param_type: FieldType,
+    #[serde(default)]
+    pub default: Option<serde_json::Value>,
+}
+
+impl DerivedParam {
+    /// True if `arg` is a SQL literal of the parameter's type.
+    fn accepts_literal(&self, arg: &Expr) -> bool {
+        fn literal(e: &Expr) -> Option<&Value> {
+            match e {
+                Expr::Value(v) => Some(&v.value),
+                Expr::UnaryOp { op: UnaryOperator::Minus, expr } => literal(expr).filter(|v| matches!(v, Value::Number(..))),
+                _ => None,
+            }
+        }
+        match (self.param_type, arg) {
+            (FieldType::StringArray, Expr::Array(array)) => {
+                array.elem.iter().all(|e| matches!(literal(e), Some(Value::SingleQuotedString(_))))
+            }
+            (ty, arg) => matches!(
+                (ty, literal(arg)),
+                (FieldType::Number, Some(Value::Number(..)))
+                    | (FieldType::Bool, Some(Value::Boolean(_)))
+                    | (FieldType::String | FieldType::Enum | FieldType::Date, Some(Value::SingleQuotedString(_)))
+            ),
+        }
+    }
+}
+
+impl DerivedField {
+    /// The derived fields `sql` reads: the `depends_on` entries that are not `entity.column`.
+    pub fn derived_dependencies(&self) -> impl Iterator<Item = &str> {
+        self.depends_on.iter().map(String::as_str).filter(|d| !d.contains('.'))
+    }
+
+    /// The `:name` slot `e` is, if it is one.
+    pub fn placeholder(e: &Expr) -> Option<&str> {
+        match e {
+            Expr::Value(v) => match &v.value {
+                Value::Placeholder(p) => Some(p.strip_prefix(':').unwrap_or(p)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Check the arguments `caller` reads this field with: at most one per param, each a
+    /// literal of its type or one of the caller's params of the same type, and a default for
+    /// every param left out.
+    fn check_call(&self, caller: &DerivedField, args: &[&Expr]) -> anyhow::Result<()> {
+        if args.len() > self.params.len() {
+            anyhow::bail!("derived field {} takes {} arguments, got {}", self.name, self.params.len(), args.len());
+        }
+        for (i, p) in self.params.iter().enumerate() {
+            let ok = match args.get(i) {
+                Some(arg) => match Self::placeholder(arg) {
+                    Some(slot) => caller.params.iter().any(|c| c.name == slot && c.param_type == p.param_type),
+                    None => p.accepts_literal(arg),
+                },
+                None if p.default.is_none() => {
+                    anyhow::bail!("derived field {} needs a value for {}, which has no default", self.name, p.name)
+                }
+                None => true,
+            };
+            if !ok {
+                anyhow::bail!(
+                    "argument {} of derived field {} must be a {:?} literal or param, got {}",
+                    p.name,
+                    self.name,
+                    p.param_type,
+                    args[i]
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse `sql`, checking that everything it reads is listed in `depends_on` (columns
+    /// written `entity.column`, derived fields by name) and that its `:name` slots are
+    /// exactly the declared `params`.
+    pub fn parse(&self) -> anyhow::Result<Expr> {
+        let mut expr = parse_expr(&self.sql)?;
+        let derived = |name: &str| self.derived_dependencies().any(|d| d == name);
+        let mut slots = Vec::new();
+        visit_exprs_mut(&mut expr, &mut |e| {
+            match e {
+                Expr::CompoundIdentifier(parts) => {
+                    let [entity, column] = parts.as_slice() else {
+                        anyhow::bail!("'{}' must be written entity.column", e);
+                    };
+                    let dependency = format!("{}.{}", entity.value, column.value);
+                    if !self.depends_on.contains(&dependency) {
+                        anyhow::bail!("reads {} without listing it in depends_on", dependency);
+                    }
+                }
+                Expr::Identifier(name) if !derived(&name.value) => anyhow::bail!(
+                    "'{}' is neither a column qualified by its entity nor a derived field in depends_on",
+                    name
+                ),
+                Expr::Function(f) if derived(&f.name.to_string()) => {
+                    function_args(f)?;
+                }
+                Expr::Value(v) => {
+                    if let Value::Placeholder(p) = &v.value {
+                        let slot = p
+                            .strip_prefix(':')
+                            .filter(|slot| self.params.iter().any(|d| d.name == *slot))
+                            .ok_or_else(|| anyhow::anyhow!("parameter '{}' is not one of its params", p))?;
+                        slots.push(slot.to_string());
+                    }
+                }
+                _ => {}
+            }
+            Ok(())
+        })
+        .and_then(|()| match self.params.iter().find(|p| !slots.contains(&p.name)) {
+            Some(unused) => anyhow::bail!("param {} is never used as :{}", unused.name, unused.name),
+            None => Ok(()),
+        })
+        .with_context(|| format!("derived field {}", self.name))?;
+        Ok(expr)
+    }
+}
+
+/// A ReportSpec field and where it lives.
+/// - `column` only: a plain column of `entity`
+/// - `column` + `json_path`: a value inside a JSON column, e.g. `$.packageId`
+/// - neither: the derived field with the same name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FieldCard {
+    pub name: String,
+    pub entity: String,
+    #[serde(default)]
+    pub column: Option<String>,
+    #[serde(default)]
+    pub json_path: Option<String>,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    pub selectable: bool,
+    pub filterable: bool,
+    pub sortable: bool,
+    /// Role of `entity` the field is read through, for entities joined more than once.
+    #[serde(default)]
+    pub role: Option<String>,
+    /// Other names users reach for, e.g. `country` for `countries`; only used for suggestions.
+    #[serde(default)]
+    pub synonyms: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,4 +590,27 @@ pub struct WorkspaceIndex {
     pub exemplar_sql_dir: String,
     pub tags: Vec<String>,
     pub entities: Vec<String>,
+    #[serde(default)]
+    pub row_limits: RowLimits,
+}
+
+/// Row caps applied by the compiler when rendering LIMIT.
+/// - `preview_default`: LIMIT used for previews that do not ask for one
+/// - `preview_max`: previews asking for more rows are clamped to this
+/// - `export_max`: exports asking for more rows are rejected
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RowLimits {
+    pub preview_default: u64,
+    pub preview_max: u64,
+    pub export_max: u64,
+}
+
+impl Default for RowLimits {
+    fn default() -> Self {
+        Self {
+            preview_default: 100,
+            preview_max: 1_000,
+            export_max: 1_000_000,
+        }
+    }
 }
@@ -1,21 +1,141 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use serde::{Deserialize, Serialize};
+use crate::schema::cards::{DerivedParam, FilterParam, SchemaCards};
 
 #[derive(Debug, Clone)]
 pub struct WorkspaceSchema {
     pub workspace: String,
     pub fields: HashMap<String, FieldDef>,
+    pub entities: BTreeSet<String>,
+    /// Names a field may be looked up by: its name, its synonyms and its entity column.
+    pub terms: Vec<(String, String)>, // (term, field)
+    pub named_filters: Vec<NamedFilterDef>,
+}
+
+/// A common filter of an entity card, as ReportSpec `{"named": ...}` filters see it.
+#[derive(Debug, Clone)]
+pub struct NamedFilterDef {
+    pub name: String,
+    pub entity: String,
+    pub params: Vec<FilterParam>, // its `:name` slots and their types
+}
+
+impl WorkspaceSchema {
+    /// Build the validation view of a workspace from its schema cards' field catalog,
+    /// the same catalog the compiler resolves fields through.
+    pub fn from_cards(cards: &SchemaCards) -> Self {
+        let fields = cards
+            .field_catalog
+            .iter()
+            .map(|f| {
+                (
+                    f.name.clone(),
+                    FieldDef {
+                        entity: f.role.clone().unwrap_or_else(|| f.entity.clone()),
+                        field_type: f.field_type,
+                        selectable: f.selectable,
+                        filterable: f.filterable,
+                        sortable: f.sortable,
+                        aggregate: cards.is_aggregate_field(&f.name),
+                        params: match f.column {
+                            Some(_) => Vec::new(),
+                            None => cards.derived_field(&f.name).map(|df| df.params.clone()).unwrap_or_default(),
+                        },
+                    },
+                )
+            })
+            .collect();
+
+        let terms = cards
+            .field_catalog
+            .iter()
+            .flat_map(|f| {
+                std::iter::once(&f.name)
+                    .chain(&f.synonyms)
+                    .chain(&f.column)
+                    .map(move |term| (term.clone(), f.name.clone()))
+            })
+            .collect();
+
+        // Registry loading rejects common filters that do not parse, so none are lost here.
+        let named_filters = cards
+            .entities
+            .iter()
+            .flat_map(|e| e.common_filters.iter().map(move |f| (e, f)))
+            .filter_map(|(e, f)| {
+                f.parse(e).ok()?;
+                Some(NamedFilterDef { name: f.name.clone(), entity: e.name.clone(), params: f.params.clone() })
+            })
+            .collect();
+
+        WorkspaceSchema {
+            workspace: cards.workspace.clone(),
+            fields,
+            entities: cards.entities.iter().map(|e| e.name.clone()).collect(),
+            terms,
+            named_filters,
+        }
+    }
+
+    /// Fields that `input` most likely meant, best first, among those `usable` allows.
+    /// Matches a field's name, synonyms and column name, exactly or by Jaro-Winkler
+    /// similarity, case-insensitively.
+    pub fn suggest_fields(&self, input: &str, usable: impl Fn(&FieldDef) -> bool) -> Vec<String> {
+        let candidates = self
+            .terms
+            .iter()
+            .filter(|(_, field)| self.fields.get(field).is_some_and(&usable))
+            .map(|(term, field)| (term.as_str(), field.as_str()));
+        rank_suggestions(input, candidates)
+    }
+
+    /// Entities that `input` most likely meant, best first.
+    pub fn suggest_entities(&self, input: &str) -> Vec<String> {
+        rank_suggestions(input, self.entities.iter().map(|e| (e.as_str(), e.as_str())))
+    }
+
+    /// Named filters that `input` most likely meant, best first.
+    pub fn suggest_named_filters(&self, input: &str) -> Vec<String> {
+        rank_suggestions(input, self.named_filters.iter().map(|f| (f.name.as_str(), f.name.as_str())))
+    }
+}
+
+const MIN_SIMILARITY: f64 = 0.84;
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Score every (term, target) pair against `input` and keep each target's best score.
+/// Ties sort by target name so the ranking is deterministic.
+fn rank_suggestions<'a>(input: &str, candidates: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<String> {
+    let input = input.to_lowercase();
+    let mut best: HashMap<&str, f64> = HashMap::new();
+    for (term, target) in candidates {
+        let score = strsim::jaro_winkler(&input, &term.to_lowercase());
+        if score >= MIN_SIMILARITY {
+            let entry = best.entry(target).or_insert(score);
+            *entry = entry.max(score);
+        }
+    }
+
+    let mut ranked: Vec<(&str, f64)> = best.into_iter().collect();
+    ranked.sort_by(|(a, x), (b, y)| y.total_cmp(x).then_with(|| a.cmp(b)));
+    ranked.into_iter().take(MAX_SUGGESTIONS).map(|(t, _)| t.to_string()).collect()
 }
 
 #[derive(Debug, Clone)]
 pub struct FieldDef {
+    pub entity: String, // join graph node: the entity, or the role it is read through
     pub field_type: FieldType,
     pub selectable: bool,
     pub filterable: bool,
     pub sortable: bool,
+    pub aggregate: bool, // already aggregates rows; never grouped or re-aggregated
+    pub params: Vec<DerivedParam>, // of a derived field; filters fill them through `args`
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FieldType {
+    #[default]
     String,
     StringArray,
     Number,
@@ -23,3 +143,29 @@ pub enum FieldType {
     Enum,
     Bool,
 }
+
+impl FieldType {
+    /// True if `v` is a JSON value of this type: a string for strings, enums and dates, and a
+    /// non-empty array of strings for string arrays.
+    pub fn accepts(self, v: &serde_json::Value) -> bool {
+        match self {
+            FieldType::String | FieldType::Enum | FieldType::Date => v.is_string(),
+            FieldType::Bool => v.is_boolean(),
+            FieldType::Number => v.is_number(),
+            FieldType::StringArray => v
+                .as_array()
+                .is_some_and(|items| !items.is_empty() && items.iter().all(serde_json::Value::is_string)),
+        }
+    }
+
+    /// What `accepts` expects, for error messages: "a string", "a date string", ...
+    pub fn expected(self) -> &'static str {
+        match self {
+            FieldType::String | FieldType::Enum => "a string",
+            FieldType::Bool => "a boolean",
+            FieldType::Number => "a number",
+            FieldType::Date => "a date string",
+            FieldType::StringArray => "a non-empty array of strings",
+        }
+    }
+}
@@ -1,8 +1,10 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use crate::schema::cards::{JoinEdge, SchemaCards};
 use anyhow::anyhow;
 
+/// The edge between two join graph nodes (entities, or roles for role edges).
 pub fn find_edge<'a>(cards: &'a SchemaCards, from: &str, to: &str) -> Option<&'a JoinEdge> {
-    cards.join_graph.edges.iter().find(|e| e.from == from && e.to == to)
+    cards.join_graph.edges.iter().find(|e| e.from == from && e.to_node() == to)
 }
 
 pub fn assert_edge_safe(cards: &SchemaCards, from: &str, to: &str) -> anyhow::Result<()> {
@@ -11,4 +13,114 @@ pub fn assert_edge_safe(cards: &SchemaCards, from: &str, to: &str) -> anyhow::Re
         return Err(anyhow!("join edge {} -> {} is marked unsafe", from, to));
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Edges connecting every `required` entity through the join graph.
+///
+/// Grows a tree from the first required entity (in `join_graph.nodes` order) and repeatedly
+/// attaches the nearest remaining required entity along its shortest path of safe edges, so
+/// bridge tables such as `campaign_offers` are pulled in automatically. Ties are broken by
+/// node and edge order, which keeps the result deterministic.
+pub fn connecting_edges<'a>(cards: &'a SchemaCards, required: &[&str]) -> anyhow::Result<Vec<&'a JoinEdge>> {
+    let node_rank = |name: &str| {
+        cards
+            .join_graph
+            .nodes
+            .iter()
+            .position(|n| n == name)
+            .unwrap_or(usize::MAX)
+    };
+
+    let mut remaining: Vec<&str> = required.to_vec();
+    remaining.sort_by_key(|n| node_rank(n));
+    remaining.dedup();
+
+    let Some(seed) = remaining.first().copied() else {
+        return Ok(vec![]);
+    };
+
+    let mut tree: BTreeSet<String> = [seed.to_string()].into_iter().collect();
+    let mut edges: Vec<&'a JoinEdge> = Vec::new();
+    remaining.retain(|n| !tree.contains(*n));
+
+    while !remaining.is_empty() {
+        let parents = shortest_paths_from(cards, &tree);
+
+        // `remaining` is already in node order, so the first minimum wins ties.
+        let target = remaining
+            .iter()
+            .filter_map(|n| parents.get(*n).map(|(_, depth)| (*n, *depth)))
+            .min_by_key(|(_, depth)| *depth)
+            .map(|(n, _)| n)
+            .ok_or_else(|| {
+                anyhow!(
+                    "no safe join path connects {} to {}",
+                    remaining.join(", "),
+                    tree.iter().cloned().collect::<Vec<_>>().join(", ")
+                )
+            })?;
+
+        let mut node = target.to_string();
+        while !tree.contains(&node) {
+            let (edge, _) = parents[&node];
+            edges.push(edge);
+            tree.insert(node.clone());
+            node = if edge.to_node() == node { edge.from.clone() } else { edge.to_node().to_string() };
+        }
+        remaining.retain(|n| !tree.contains(*n));
+    }
+
+    Ok(edges)
+}
+
+/// Shortest path of safe edges from `target` to any of the `sources`, listed starting at
+/// `target`. Used to correlate an EXISTS subquery on `target` with the outer query.
+pub fn path_to<'a>(cards: &'a SchemaCards, sources: &BTreeSet<String>, target: &str) -> anyhow::Result<Vec<&'a JoinEdge>> {
+    let parents = shortest_paths_from(cards, sources);
+
+    let mut path = Vec::new();
+    let mut node = target.to_string();
+    while !sources.contains(&node) {
+        let (edge, _) = parents.get(&node).ok_or_else(|| {
+            anyhow!(
+                "no safe join path connects {} to {}",
+                target,
+                sources.iter().cloned().collect::<Vec<_>>().join(", ")
+            )
+        })?;
+        path.push(*edge);
+        node = if edge.to_node() == node { edge.from.clone() } else { edge.to_node().to_string() };
+    }
+
+    Ok(path)
+}
+
+/// Breadth-first search over safe edges (in either direction) starting from every node in
+/// `sources`. Maps each reached node to the edge it was reached through and its depth.
+fn shortest_paths_from<'a>(
+    cards: &'a SchemaCards,
+    sources: &BTreeSet<String>,
+) -> HashMap<String, (&'a JoinEdge, usize)> {
+    let mut parents: HashMap<String, (&'a JoinEdge, usize)> = HashMap::new();
+    let mut queue: VecDeque<(String, usize)> = sources.iter().map(|s| (s.clone(), 0)).collect();
+
+    while let Some((node, depth)) = queue.pop_front() {
+        for edge in cards.join_graph.edges.iter().filter(|e| e.safe) {
+            let next = if edge.from == node {
+                edge.to_node()
+            } else if edge.to_node() == node {
+                &edge.from
+            } else {
+                continue;
+            };
+
+            if sources.contains(next) || parents.contains_key(next) {
+                continue;
+            }
+            parents.insert(next.to_string(), (edge, depth + 1));
+            queue.push_back((next.to_string(), depth + 1));
+        }
+    }
+
+    parents
+}
@@ -1,23 +1,111 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::dsl::report_spec::ReportSpec;
 use crate::schema::cards::{SchemaCards, WorkspaceIndex};
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
 pub struct SchemaRegistry {
     pub index: WorkspaceIndex,
     pub cards: SchemaCards,
+    /// `index.exemplar_sql_dir`, resolved against the index file's directory.
+    #[serde(default)]
+    pub exemplar_dir: PathBuf,
 }
 
 impl SchemaRegistry {
-    pub fn load(index_path: &str) -> anyhow::Result<Self> {
+    /// Load a workspace index and its schema cards.
+    /// `schema_cards_path` and `exemplar_sql_dir` are resolved relative to the directory of
+    /// the index file, so loading does not depend on the working directory.
+    pub fn load(index_path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let index_path = index_path.as_ref();
         let idx = std::fs::read_to_string(index_path)
-            .with_context(|| format!("read workspace index: {}", index_path))?;
-        let index: WorkspaceIndex = serde_json::from_str(&idx)?;
+            .with_context(|| format!("read workspace index: {}", index_path.display()))?;
+        let index: WorkspaceIndex = serde_json::from_str(&idx)
+            .with_context(|| format!("parse workspace index: {}", index_path.display()))?;
+
+        let index_dir = index_path.parent().unwrap_or_else(|| Path::new(""));
+        let cards_path = index_dir.join(&index.schema_cards_path);
+        let exemplar_dir = index_dir.join(&index.exemplar_sql_dir);
+        let cards_raw = std::fs::read_to_string(&cards_path)
+            .with_context(|| format!("read schema cards: {}", cards_path.display()))?;
+        let mut cards: SchemaCards = serde_json::from_str(&cards_raw)
+            .with_context(|| format!("parse schema cards: {}", cards_path.display()))?;
+        cards
+            .expand_json_paths()
+            .with_context(|| format!("expand json paths: {}", cards_path.display()))?;
+        cards
+            .check_common_filters()
+            .with_context(|| format!("check common filters: {}", cards_path.display()))?;
+        cards
+            .check_derived_fields()
+            .with_context(|| format!("check derived fields: {}", cards_path.display()))?;
+
+        Ok(Self { index, cards, exemplar_dir })
+    }
 
-        let cards_raw = std::fs::read_to_string(&index.schema_cards_path)
-            .with_context(|| format!("read schema cards: {}", index.schema_cards_path))?;
-        let cards: SchemaCards = serde_json::from_str(&cards_raw)?;
+    /// The workspace's exemplar `*.sql` files, sorted by path.
+    pub fn exemplar_sql_files(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut files = std::fs::read_dir(&self.exemplar_dir)
+            .with_context(|| format!("read exemplar dir: {}", self.exemplar_dir.display()))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        files.retain(|p| p.extension().is_some_and(|ext| ext == "sql"));
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// Every workspace registry found in a config directory, keyed by workspace name.
+#[derive(Debug, Default)]
+pub struct WorkspaceCatalog {
+    registries: BTreeMap<String, SchemaRegistry>,
+}
+
+impl WorkspaceCatalog {
+    /// Load every `*.index.json` in `dir` (e.g. `config/workspaces`).
+    pub fn load_dir(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let dir = dir.as_ref();
+        let mut index_paths = std::fs::read_dir(dir)
+            .with_context(|| format!("read workspace config dir: {}", dir.display()))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        index_paths.retain(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.ends_with(".index.json"))
+        });
+        index_paths.sort();
+
+        index_paths
+            .iter()
+            .try_fold(WorkspaceCatalog::default(), |mut catalog, path| {
+                let reg = SchemaRegistry::load(path)?;
+                let workspace = reg.index.workspace.clone();
+                if catalog.registries.insert(workspace.clone(), reg).is_some() {
+                    return Err(anyhow!(
+                        "workspace '{}' is defined more than once in {}",
+                        workspace,
+                        dir.display()
+                    ));
+                }
+                Ok(catalog)
+            })
+    }
+
+    pub fn get(&self, workspace: &str) -> Option<&SchemaRegistry> {
+        self.registries.get(workspace)
+    }
+
+    /// The registry for the workspace a ReportSpec targets.
+    pub fn registry_for(&self, spec: &ReportSpec) -> anyhow::Result<&SchemaRegistry> {
+        self.get(&spec.workspace)
+            .ok_or_else(|| anyhow!("workspace '{}' not found", spec.workspace))
+    }
 
-        Ok(Self { index, cards })
+    pub fn workspaces(&self) -> impl Iterator<Item = &str> {
+        self.registries.keys().map(String::as_str)
     }
 }
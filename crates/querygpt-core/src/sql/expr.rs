@@ -0,0 +1,186 @@
+use anyhow::{anyhow, bail, Context, Result};
+use sqlparser::ast::{Expr, Function, FunctionArg, FunctionArgExpr, FunctionArgumentClause, FunctionArguments};
+use sqlparser::ast::Spanned;
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Location, Span};
+
+/// Parse a single SQL expression, e.g. a schema card's `legacy::jsonb ->> 'phase_type' = 'PREPAID'`.
+pub fn parse_expr(sql: &str) -> Result<Expr> {
+    let expr = Parser::new(&PostgreSqlDialect {})
+        .try_with_sql(sql)
+        .and_then(|mut p| {
+            let expr = p.parse_expr()?;
+            p.expect_token(&sqlparser::tokenizer::Token::EOF)?;
+            Ok(expr)
+        })
+        .with_context(|| format!("parse SQL expression '{}'", sql))?;
+    Ok(expr)
+}
+
+/// Call `visit` on every subexpression of `expr` and then on `expr` itself, children before
+/// parents, so an expression `visit` replaces is never walked again.
+///
+/// Covers the scalar expressions schema cards use (operators, casts, CASE, function calls,
+/// IN lists, arrays, ...). Anything else, subqueries in particular, is an error rather than
+/// being skipped, so no column reference can slip past a caller that checks or rewrites them.
+pub fn visit_exprs_mut(expr: &mut Expr, visit: &mut dyn FnMut(&mut Expr) -> Result<()>) -> Result<()> {
+    for child in children_mut(expr)? {
+        visit_exprs_mut(child, visit)?;
+    }
+    visit(expr)
+}
+
+/// Rewrite `sql` by replacing each subexpression `replace` returns text for, parents before
+/// children; a replaced subexpression is not walked into. Everything else is kept exactly as
+/// written, so `::date` stays lowercase and a card's spacing survives.
+///
+/// Walks the same expressions as `visit_exprs_mut` and fails on the same ones.
+pub fn rewrite_sql(sql: &str, replace: &mut dyn FnMut(&Expr) -> Result<Option<String>>) -> Result<String> {
+    fn collect(
+        expr: &mut Expr,
+        replace: &mut dyn FnMut(&Expr) -> Result<Option<String>>,
+        edits: &mut Vec<(Span, bool, String)>,
+    ) -> Result<()> {
+        if let Some(text) = replace(expr)? {
+            let span = expr.span();
+            if span == Span::empty() {
+                bail!("cannot locate '{}' in its SQL", expr);
+            }
+            edits.push((span, matches!(expr, Expr::Function(_)), text));
+            return Ok(());
+        }
+        for child in children_mut(expr)? {
+            collect(child, replace, edits)?;
+        }
+        Ok(())
+    }
+
+    let mut expr = parse_expr(sql)?;
+    let mut edits = Vec::new();
+    collect(&mut expr, replace, &mut edits)?;
+
+    // Splice from the end so earlier offsets stay valid.
+    edits.sort_by_key(|(span, _, _)| std::cmp::Reverse(span.start));
+    let mut out = sql.to_string();
+    for (span, call, text) in edits {
+        let start = byte_offset(sql, span.start)?;
+        // A call's span stops at its last argument, before the closing parenthesis.
+        let end = if call {
+            call_end(sql, start).ok_or_else(|| anyhow!("unbalanced parentheses in '{}'", sql))?
+        } else {
+            byte_offset(sql, span.end)?
+        };
+        out.replace_range(start..end, &text);
+    }
+    Ok(out)
+}
+
+/// Byte offset just past the `)` closing the first argument list at or after `start`.
+/// Parentheses inside quoted literals and identifiers are skipped.
+fn call_end(sql: &str, start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut quote = None;
+    for (i, c) in sql[start..].char_indices() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(start + i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Byte offset of a parser location (1-based line and character column) in `sql`.
+fn byte_offset(sql: &str, location: Location) -> Result<usize> {
+    let line_start: usize = sql.split_inclusive('\n').take(location.line.saturating_sub(1) as usize).map(str::len).sum();
+    sql[line_start..]
+        .char_indices()
+        .map(|(i, _)| line_start + i)
+        .chain([sql.len()])
+        .nth(location.column.saturating_sub(1) as usize)
+        .ok_or_else(|| anyhow::anyhow!("location {} is outside '{}'", location, sql))
+}
+
+fn children_mut(expr: &mut Expr) -> Result<Vec<&mut Expr>> {
+    Ok(match expr {
+        Expr::Identifier(_) | Expr::CompoundIdentifier(_) | Expr::Value(_) | Expr::TypedString(_) => vec![],
+        Expr::BinaryOp { left, right, .. }
+        | Expr::AnyOp { left, right, .. }
+        | Expr::AllOp { left, right, .. }
+        | Expr::IsDistinctFrom(left, right)
+        | Expr::IsNotDistinctFrom(left, right) => vec![left, right],
+        Expr::Like { expr, pattern, .. } | Expr::ILike { expr, pattern, .. } => vec![expr, pattern],
+        Expr::UnaryOp { expr, .. }
+        | Expr::Nested(expr)
+        | Expr::Cast { expr, .. }
+        | Expr::Extract { expr, .. }
+        | Expr::IsNull(expr)
+        | Expr::IsNotNull(expr)
+        | Expr::IsTrue(expr)
+        | Expr::IsNotTrue(expr)
+        | Expr::IsFalse(expr)
+        | Expr::IsNotFalse(expr) => vec![expr],
+        Expr::Between { expr, low, high, .. } => vec![expr, low, high],
+        Expr::InList { expr, list, .. } => std::iter::once(&mut **expr).chain(list.iter_mut()).collect(),
+        Expr::Array(array) => array.elem.iter_mut().collect(),
+        Expr::Interval(interval) => vec![&mut interval.value],
+        Expr::Case { operand, conditions, else_result, .. } => operand
+            .iter_mut()
+            .map(|e| &mut **e)
+            .chain(conditions.iter_mut().flat_map(|w| [&mut w.condition, &mut w.result]))
+            .chain(else_result.iter_mut().map(|e| &mut **e))
+            .collect(),
+        Expr::Function(f) => {
+            if f.over.is_some() {
+                bail!("window functions are not supported: {}", f);
+            }
+            if matches!(f.args, FunctionArguments::Subquery(_)) {
+                bail!("subqueries are not supported: {}", f);
+            }
+            let FunctionArguments::List(args) = &mut f.args else {
+                return Ok(vec![]);
+            };
+            let mut exprs = Vec::new();
+            for arg in &mut args.args {
+                match arg {
+                    FunctionArg::Named { arg: FunctionArgExpr::Expr(e), .. }
+                    | FunctionArg::ExprNamed { arg: FunctionArgExpr::Expr(e), .. }
+                    | FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) => exprs.push(e),
+                    _ => {}
+                }
+            }
+            for clause in &mut args.clauses {
+                if let FunctionArgumentClause::OrderBy(order_by) = clause {
+                    exprs.extend(order_by.iter_mut().map(|o| &mut o.expr));
+                }
+            }
+            exprs.extend(f.filter.iter_mut().map(|e| &mut **e));
+            exprs
+        }
+        other => bail!("unsupported SQL expression: {}", other),
+    })
+}
+
+/// The arguments of a plain call `f(a, b)`, in order.
+pub fn function_args(f: &Function) -> Result<Vec<&Expr>> {
+    let args = match &f.args {
+        FunctionArguments::None => return Ok(vec![]),
+        FunctionArguments::List(list) if list.duplicate_treatment.is_none() && list.clauses.is_empty() => &list.args,
+        _ => bail!("expected plain arguments in {}", f),
+    };
+    args.iter()
+        .map(|arg| match arg {
+            FunctionArg::Unnamed(FunctionArgExpr::Expr(e)) => Ok(e),
+            _ => Err(anyhow!("expected positional arguments in {}", f)),
+        })
+        .collect()
+}
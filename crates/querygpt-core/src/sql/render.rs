@@ -1,18 +1,14 @@
 
 use std::collections::BTreeSet;
 use anyhow::{anyhow, Result};
-use crate::dsl::plan::{IntermediatePlan, PlanJoin, JoinType, SortDirection};
+use serde::Serialize;
+use crate::dsl::plan::{IntermediatePlan, PlanJoin, PlanOrder, PlanRank, JoinType, SortDirection, SqlParam};
 
 
 
 
-fn render_order_by(plan: &IntermediatePlan) -> String {
-    if plan.order_by.is_empty() {
-        return String::new();
-    }
-
-    let items = plan
-        .order_by
+fn render_order_items(order_by: &[PlanOrder], separator: &str) -> String {
+    order_by
         .iter()
         .map(|o| {
             let dir = match o.direction {
@@ -22,78 +18,95 @@ fn render_order_by(plan: &IntermediatePlan) -> String {
             format!("{} {}", o.expression, dir)
         })
         .collect::<Vec<_>>()
-        .join(",\n         ");
-
-    format!("\nORDER BY {}", items)
+        .join(separator)
 }
 
+fn render_order_by(plan: &IntermediatePlan) -> String {
+    if plan.order_by.is_empty() {
+        return String::new();
+    }
 
-fn is_aggregate_expr(expr: &str) -> bool {
-    // Minimal heuristics good enough for v1:
-    // You can extend later (COUNT, SUM, MIN, MAX, AVG, ARRAY_AGG, BOOL_AND, etc.)
-    let upper = expr.to_ascii_uppercase();
-    upper.contains("STRING_AGG(")
-        || upper.contains("COUNT(")
-        || upper.contains("SUM(")
-        || upper.contains("MIN(")
-        || upper.contains("MAX(")
-        || upper.contains("AVG(")
-        || upper.contains("ARRAY_AGG(")
+    format!("\nORDER BY {}", render_order_items(&plan.order_by, ",\n         "))
 }
-fn group_by_exprs(plan: &IntermediatePlan) -> Vec<String> {
-    let has_agg = plan.projections.iter().any(|p| is_aggregate_expr(&p.expression));
 
-    if !has_agg {
-        return vec![];
-    }
+/// `ROW_NUMBER() OVER (PARTITION BY ... ORDER BY ...) AS rank`
+fn render_rank_column(rank: &PlanRank) -> String {
+    let partition = if rank.partition_by.is_empty() {
+        String::new()
+    } else {
+        format!("PARTITION BY {} ", rank.partition_by.join(", "))
+    };
+    format!(
+        "ROW_NUMBER() OVER ({}ORDER BY {}) AS {}",
+        partition,
+        render_order_items(&rank.order_by, ", "),
+        rank.alias
+    )
+}
 
-    plan.projections
+/// Wrap the ranked query in a CTE and read its named output columns back, keeping the
+/// first `top_n` rows per partition.
+fn render_ranked(plan: &IntermediatePlan, rank: &PlanRank, inner: &str) -> Result<String> {
+    let columns = plan
+        .projections
         .iter()
-        .filter(|p| !is_aggregate_expr(&p.expression))
-        .map(|p| p.expression.clone())
-        .collect()
+        .map(|p| p.alias.clone().ok_or_else(|| anyhow!("ranked projection '{}' has no output name", p.field)))
+        .chain(std::iter::once(Ok(rank.alias.clone())))
+        .collect::<Result<Vec<_>>>()?
+        .join(",\n       ");
+    let top_n = rank
+        .top_n
+        .map(|n| format!("\nWHERE {} <= {}", rank.alias, n))
+        .unwrap_or_default();
+
+    Ok(format!(
+        "WITH ranked AS (\n{inner}\n)\nSELECT {columns}\nFROM ranked{top_n}{order_by}{pagination}",
+        inner = inner,
+        columns = columns,
+        top_n = top_n,
+        order_by = render_order_by(plan),
+        pagination = render_pagination(plan)
+    ))
 }
-fn render_group_by(plan: &IntermediatePlan) -> String {
-    let exprs = group_by_exprs(plan);
-    if exprs.is_empty() {
-        "".to_string()
-    } else {
-        format!("\nGROUP BY {}", exprs.join(",\n         "))
-    }
+
+
+fn render_profile_scope(plan: &IntermediatePlan) -> Option<String> {
+    plan.profile_scope.as_ref().map(|scope| {
+        if scope.profiles.len() == 1 {
+            format!("{}.{} = $1", scope.alias, scope.column)
+        } else {
+            format!("{}.{} = ANY($1)", scope.alias, scope.column)
+        }
+    })
 }
 
+fn render_pagination(plan: &IntermediatePlan) -> String {
+    let limit = plan.limit.map(|n| format!("\nLIMIT {}", n)).unwrap_or_default();
+    let offset = plan.offset.map(|n| format!("\nOFFSET {}", n)).unwrap_or_default();
+    format!("{}{}", limit, offset)
+}
 
 
-fn choose_root_alias(plan: &IntermediatePlan) -> Result<String> {
-    let table_aliases: BTreeSet<String> =
-        plan.tables.iter().map(|t| t.alias.clone()).collect();
+fn render_group_by(plan: &IntermediatePlan) -> String {
+    if plan.group_by.is_empty() {
+        return String::new();
+    }
 
-    let right_aliases: BTreeSet<String> =
-        plan.joins.iter().map(|j| j.right_alias.clone()).collect();
+    let exprs = plan.group_by.iter().map(|g| g.expression.as_str()).collect::<Vec<_>>();
+    format!("\nGROUP BY {}", exprs.join(",\n         "))
+}
 
-    let left_aliases: BTreeSet<String> =
-        plan.joins.iter().map(|j| j.left_alias.clone()).collect();
-    
-    // First try: tables that are never joined TO (traditional root selection)
-    let traditional_roots: BTreeSet<String> = table_aliases.difference(&right_aliases).cloned().collect();
-    
-    // If we have joins, prefer a root that appears as a left_alias (can start a join chain)
-    if !plan.joins.is_empty() {
-        let viable_roots: BTreeSet<String> = traditional_roots.intersection(&left_aliases).cloned().collect();
-        
-        if let Some(root) = viable_roots.iter().next() {
-            return Ok(root.clone());
-        }
+fn render_having(plan: &IntermediatePlan) -> String {
+    if plan.having.is_empty() {
+        return String::new();
     }
-    
-    // Fallback to any traditional root
-    traditional_roots
-        .iter()
-        .next()
-        .cloned()
-        .ok_or_else(|| anyhow!("cannot determine root alias: plan has no tables or join graph is cyclic"))
+
+    let predicates = plan.having.iter().map(|h| h.expression.as_str()).collect::<Vec<_>>();
+    format!("\nHAVING {}", predicates.join("\n   AND "))
 }
 
+
+
 fn sorted_joins(mut joins: Vec<PlanJoin>) -> Vec<PlanJoin> {
     // (If you want absolutely no `mut` anywhere, see note below.)
     joins.sort_by(|a, b| {
@@ -146,7 +159,7 @@ fn order_joins(plan: &IntermediatePlan, root: &str) -> Result<Vec<PlanJoin>> {
 
         let acc2: Vec<PlanJoin> = acc
             .into_iter()
-            .chain(ready.into_iter())
+            .chain(ready)
             .collect();
 
         step(visited2, not_ready, acc2)
@@ -162,7 +175,7 @@ fn order_joins(plan: &IntermediatePlan, root: &str) -> Result<Vec<PlanJoin>> {
 
 
 fn render_sql_inner(plan: &IntermediatePlan) -> Result<String> {
-    let select_clause = if plan.projections.is_empty() {
+    let select_clause = if plan.projections.is_empty() && plan.rank.is_none() {
         "SELECT 1".to_string()
     } else {
         let cols = plan.projections
@@ -174,13 +187,17 @@ fn render_sql_inner(plan: &IntermediatePlan) -> Result<String> {
                     p.expression.clone()
                 }
             })
+            .chain(plan.rank.iter().flat_map(|r| &r.keys).map(|k| {
+                format!("{} AS {}", k.expression, k.alias.as_deref().unwrap_or_default())
+            }))
+            .chain(plan.rank.as_ref().map(render_rank_column))
             .collect::<Vec<_>>()
             .join(",\n       ");
         format!("SELECT {}", cols)
     };
 
     // FROM (deterministic + valid)
-    let root_alias = choose_root_alias(plan)?;
+    let root_alias = plan.root_alias()?;
     let root_table = plan.tables.iter().find(|t| t.alias == root_alias)
         .ok_or_else(|| anyhow!("root alias '{}' not found in plan.tables", root_alias))?;
     let from_clause = format!("FROM {} {}", root_table.name, root_table.alias);
@@ -197,19 +214,22 @@ fn render_sql_inner(plan: &IntermediatePlan) -> Result<String> {
             let on_clause = j.conditions
                 .iter()
                 .map(|c| format!("{} = {}", c.left_field, c.right_field))
+                .chain(j.predicates.iter().cloned())
                 .collect::<Vec<_>>()
                 .join(" AND ");
 
-            let right_table_name = plan
+            let right_table = plan
                 .tables
                 .iter()
                 .find(|t| t.alias == j.right_alias)
                 .ok_or_else(|| anyhow!(
                 "join right_alias '{}' not found in plan.tables",
                 j.right_alias
-            ))?
-                .name
-                .clone();
+            ))?;
+            let right_table_name = match &right_table.subquery {
+                Some(subquery) => format!("({})", subquery),
+                None => right_table.name.clone(),
+            };
 
             Ok(format!(
                 "{} {} {} ON {}",
@@ -223,33 +243,116 @@ fn render_sql_inner(plan: &IntermediatePlan) -> Result<String> {
         .join("\n");
 
 
-    let where_clause = if plan.filters.is_empty() {
-        "".to_string()
-    } else {
-        let predicates = plan.filters
-            .iter()
-            .map(|f| f.expression.clone())
-            .collect::<Vec<_>>()
-            .join("\n  AND ");
-        format!("\nWHERE {}", predicates)
+    let where_clause = {
+        let predicates = render_profile_scope(plan)
+            .into_iter()
+            .chain(plan.filters.iter().map(|f| f.expression.clone()))
+            .collect::<Vec<_>>();
+        if predicates.is_empty() {
+            "".to_string()
+        } else {
+            format!("\nWHERE {}", predicates.join("\n  AND "))
+        }
     };
     let group_by_clause = render_group_by(plan);
-    let order_by_clause = render_order_by(plan);
-    let final_sql = format!(
-        "{select}\n{from}\n{joins}{where}{group_by}{order_by}",
+    let having_clause = render_having(plan);
+    let body = format!(
+        "{select}\n{from}\n{joins}{where}{group_by}{having}",
         select = select_clause,
         from = from_clause,
         joins = if join_sql.is_empty() { "".into() } else { format!("\n{}", join_sql) },
         where = where_clause,
         group_by = group_by_clause,
-        order_by = order_by_clause
+        having = having_clause,
     );
+    let final_sql = match &plan.rank {
+        Some(rank) => render_ranked(plan, rank, &body)?,
+        None => format!("{}{}{}", body, render_order_by(plan), render_pagination(plan)),
+    };
     Ok(final_sql)
 }
 
 
 
-/// Stub: Render an intermediate plan into SQL, optionally with an LLM filling in details.
-pub fn render_sql(plan: &IntermediatePlan) -> Result<String> {
-    render_sql_inner(plan)
+/// SQL with `$1..$n` placeholders and the values to bind to them, in order.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RenderedSql {
+    pub sql: String,
+    pub params: Vec<SqlParam>,
+}
+
+/// Bind values for the rendered SQL: the profile scope as $1, then the plan's filter params.
+fn render_params(plan: &IntermediatePlan) -> Result<Vec<SqlParam>> {
+    let profile = match &plan.profile_scope {
+        Some(scope) => match scope.profiles.as_slice() {
+            [single] => Some(SqlParam::Text(single.clone())),
+            many => Some(SqlParam::TextArray(many.to_vec())),
+        },
+        None if plan.params.is_empty() => None,
+        None => return Err(anyhow!("plan has filter parameters but no profile scope to bind as $1")),
+    };
+
+    Ok(profile.into_iter().chain(plan.params.iter().cloned()).collect())
+}
+
+/// Replace each `$n` placeholder with `params[n - 1]` as a literal. Only placeholders Postgres
+/// would bind are replaced: a `$n` inside a quoted string or identifier (`"col$1"`), a
+/// dollar-quoted string (`$$...$$`, `$tag$...$tag$`) or a comment is kept as written.
+fn inline_params(sql: &str, params: &[SqlParam]) -> Result<String> {
+    let mut out = String::with_capacity(sql.len());
+    let mut rest = sql;
+
+    while let Some(ch) = rest.chars().next() {
+        // Length of the token at the start of `rest` that is copied unchanged.
+        let keep = match ch {
+            // a doubled '' or "" ends one token and starts the next, which is what we want
+            '\'' | '"' => rest[1..].find(ch).map_or(rest.len(), |i| i + 2),
+            '-' if rest.starts_with("--") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => rest[2..].find("*/").map_or(rest.len(), |i| i + 4),
+            '$' if rest[1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                let digits = rest[1..].find(|c: char| !c.is_ascii_digit()).map_or(rest.len(), |i| i + 1);
+                let n: usize = rest[1..digits].parse()?;
+                let param = n
+                    .checked_sub(1)
+                    .and_then(|i| params.get(i))
+                    .ok_or_else(|| anyhow!("placeholder ${} has no bind value", n))?;
+                out.push_str(&param.to_sql_literal());
+                rest = &rest[digits..];
+                continue;
+            }
+            '$' => match dollar_quote_tag(rest) {
+                Some(tag) => rest[tag.len()..].find(tag).map_or(rest.len(), |i| i + 2 * tag.len()),
+                None => 1,
+            },
+            ch => ch.len_utf8(),
+        };
+        out.push_str(&rest[..keep]);
+        rest = &rest[keep..];
+    }
+
+    Ok(out)
+}
+
+/// The opening tag of a dollar-quoted string at the start of `sql`: `$$` or `$tag$`, where
+/// the tag is an identifier (so never a `$n` placeholder).
+fn dollar_quote_tag(sql: &str) -> Option<&str> {
+    let body = &sql[1..];
+    let len = body.find(|c: char| !(c.is_alphanumeric() || c == '_'))?;
+    let starts_ok = body.chars().next().is_some_and(|c| !c.is_ascii_digit());
+    (body[len..].starts_with('$') && starts_ok).then(|| &sql[..len + 2])
+}
+
+/// Render an intermediate plan into parameterized SQL for execution (e.g. tokio-postgres).
+pub fn render_sql(plan: &IntermediatePlan) -> Result<RenderedSql> {
+    Ok(RenderedSql {
+        sql: render_sql_inner(plan)?,
+        params: render_params(plan)?,
+    })
+}
+
+/// Render an intermediate plan with its parameters inlined as literals.
+/// For display and snapshot tests only; execute the output of `render_sql` instead.
+pub fn render_sql_inline(plan: &IntermediatePlan) -> Result<String> {
+    let rendered = render_sql(plan)?;
+    inline_params(&rendered.sql, &rendered.params)
 }
@@ -1,6 +1,14 @@
+// Each test binary uses its own subset of these helpers.
+#![allow(dead_code)]
+
 use std::fs;
+use anyhow::Result;
+use querygpt_core::dsl::compile::{compile_report_spec, CompileContext};
+use querygpt_core::dsl::plan::IntermediatePlan;
 use querygpt_core::dsl::report_spec::ReportSpec;
+use querygpt_core::schema::cards::{DerivedField, EntityCard, FieldCard};
 use querygpt_core::schema::registry::SchemaRegistry;
+use serde_json::{json, Value};
 
 pub fn load_fixture(name: &str) -> ReportSpec {
     let path = format!("tests/fixtures/report_specs/{}", name);
@@ -11,4 +19,46 @@ pub fn load_fixture(name: &str) -> ReportSpec {
 pub fn load_schema_registry(name: &str) -> SchemaRegistry {
     let path = format!("../../config/workspaces/{}", name);
     SchemaRegistry::load(&path).expect("load schema registry")
-}
\ No newline at end of file
+}
+
+/// A campaigns_offers export spec. `select` items are select item objects, or bare field
+/// names for plain fields; `filters` is the filters array.
+pub fn spec(select: Value, filters: Value) -> ReportSpec {
+    let select: Vec<Value> = select
+        .as_array()
+        .expect("select array")
+        .iter()
+        .map(|item| match item {
+            Value::String(field) => json!({ "field": field }),
+            item => item.clone(),
+        })
+        .collect();
+    serde_json::from_value(json!({
+        "version": 1,
+        "workspace": "campaigns_offers",
+        "select": select,
+        "filters": filters,
+        "mode": "export"
+    }))
+    .expect("parse spec")
+}
+
+/// Compile `spec` for the `main` profile.
+pub fn compile(registry: &SchemaRegistry, spec: &ReportSpec) -> Result<IntermediatePlan> {
+    compile_report_spec(registry, spec, &CompileContext::for_profile("main"))
+}
+
+/// Edit the entity card `name` in place.
+pub fn with_entity(registry: &mut SchemaRegistry, name: &str, edit: impl FnOnce(&mut EntityCard)) {
+    edit(registry.cards.entities.iter_mut().find(|e| e.name == name).expect("entity card"));
+}
+
+/// Edit the field catalog entry `name` in place.
+pub fn with_field(registry: &mut SchemaRegistry, name: &str, edit: impl FnOnce(&mut FieldCard)) {
+    edit(registry.cards.field_catalog.iter_mut().find(|f| f.name == name).expect("field card"));
+}
+
+/// Edit the derived field `name` in place.
+pub fn with_derived_field(registry: &mut SchemaRegistry, name: &str, edit: impl FnOnce(&mut DerivedField)) {
+    edit(registry.cards.derived_fields.iter_mut().find(|df| df.name == name).expect("derived field"));
+}
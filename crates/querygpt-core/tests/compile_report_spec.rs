@@ -1,8 +1,18 @@
-use querygpt_core::dsl::compile::compile_report_spec;
+use querygpt_core::dsl::compile::{compile_report_spec, compile_with_diagnostics, CompileContext};
+use querygpt_core::dsl::dates::FixedClock;
+use querygpt_core::dsl::diagnostics::Severity;
+use querygpt_core::dsl::fan_out::FanOutPolicy;
+use querygpt_core::dsl::plan::SqlParam;
+use querygpt_core::dsl::report_spec::{DeletedMode, ReportSpec};
+use querygpt_core::schema::cards::{DerivedField, DerivedParam, FieldCard, FilterHint, FilterParam, JoinEdge, JsonPathCard, VersionRule};
+use querygpt_core::sql::render::render_sql_inline;
+use querygpt_core::schema::field_catalog::FieldType;
+use querygpt_core::schema::registry::SchemaRegistry;
+use serde_json::json;
 
 mod common;
 
-use crate::common::{load_fixture, load_schema_registry};
+use crate::common::{compile, load_fixture, load_schema_registry, spec, with_entity, with_field};
 
 
 
@@ -23,7 +33,7 @@ fn compile_prepaid_apac_export() {
     // 3. Compile the spec into an IntermediatePlan.
     //    At this point, compile_report_spec should not return `Ok(())`,
     //    but rather an actual plan structure.
-    let plan = compile_report_spec(&registry, &spec)
+    let plan = compile_report_spec(&registry, &spec, &CompileContext::for_profile("main"))
         .expect("compile report spec");
 
     // 4. Use a snapshot assertion to lock down the plan structure.
@@ -31,3 +41,720 @@ fn compile_prepaid_apac_export() {
     //    all tables, joins, selected fields, filters, and ordering.
     insta::assert_json_snapshot!(plan);
 }
+
+/// offer_id + campaign_name only touch offers_latest and campaigns_latest; the compiler
+/// must route through the campaign_offers bridge instead of producing a cross product.
+#[test]
+fn compile_adds_bridge_table_for_join_path() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let plan = compile(&registry, &spec(json!(["offer_id", "campaign_name"]), json!([]))).expect("compile report spec");
+
+    insta::assert_json_snapshot!(plan);
+}
+
+#[test]
+fn compile_fails_when_no_safe_join_path_exists() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    registry
+        .cards
+        .join_graph
+        .edges
+        .iter_mut()
+        .filter(|e| e.to == "campaign_offers")
+        .for_each(|e| e.safe = false);
+
+    let err = compile(&registry, &spec(json!(["offer_id", "campaign_name"]), json!([]))).unwrap_err();
+    assert!(err.to_string().contains("no safe join path"), "{err}");
+}
+
+/// Fields come from the schema cards' field catalog, so a new catalog entry compiles
+/// without any compiler change.
+#[test]
+fn compile_uses_field_catalog_entries() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    registry.cards.field_catalog.push(FieldCard {
+        name: "offer_version".into(),
+        entity: "offers_latest".into(),
+        column: Some("version".into()),
+        field_type: FieldType::Number,
+        selectable: true,
+        filterable: true,
+        sortable: true,
+        ..Default::default()
+    });
+
+    let spec = spec(
+        json!(["offer_id", "offer_version"]),
+        json!([{ "field": "promo_type", "op": "eq", "value": "PREPAID" }]),
+    );
+
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    let projections: Vec<&str> = plan.projections.iter().map(|p| p.expression.as_str()).collect();
+    assert_eq!(projections, ["o.id", "o.version"]);
+    let filters: Vec<&str> = plan.filters.iter().map(|f| f.expression.as_str()).collect();
+    assert_eq!(filters, ["o.deleted = false", "op.legacy ->> 'phase_type' = $2"]);
+}
+
+#[test]
+fn compile_rejects_spec_that_fails_validation() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.order_by.push(serde_json::from_value(serde_json::json!({ "field": "countries", "dir": "asc" })).unwrap());
+
+    let err = compile(&registry, &spec).unwrap_err();
+    assert!(err.to_string().contains("field 'countries' is not sortable"), "{err}");
+}
+
+#[test]
+fn compile_renders_extended_filter_operators() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = spec(
+        json!(["offer_id"]),
+        json!([
+            { "field": "workflow_status", "op": "neq", "value": "DRAFT" },
+            { "field": "workflow_status", "op": "not_in", "value": ["ARCHIVED", "REJECTED"] },
+            { "field": "offer_start_date", "op": "gt", "value": "2025-01-01" },
+            { "field": "offer_start_date", "op": "lt", "value": "2026-01-01" },
+            { "field": "offer_end_date", "op": "between", "value": ["2025-01-01", "2025-12-31"] },
+            { "field": "offer_end_date", "op": "is_not_null" },
+            { "field": "countries", "op": "is_null" },
+            { "field": "countries", "op": "contains", "value": ["KR", "JP"] },
+            { "field": "offer_name", "op": "starts_with", "value": "50%_off" },
+            { "field": "campaign_name", "op": "ilike", "value": "%o'brien%" }
+        ]),
+    );
+
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    let filters: Vec<&str> = plan.filters.iter().map(|f| f.expression.as_str()).collect();
+    assert_eq!(
+        filters,
+        [
+            "o.deleted = false",
+            "o.status <> $2",
+            "o.status NOT IN ($3, $4)",
+            "o.start_date > $5",
+            "o.start_date < $6",
+            "o.end_date BETWEEN $7 AND $8",
+            "o.end_date IS NOT NULL",
+            "o.countries IS NULL",
+            "o.countries @> ARRAY[$9, $10]",
+            "o.name LIKE $11",
+            "c.name ILIKE $12",
+        ]
+    );
+    assert_eq!(
+        plan.params,
+        [
+            SqlParam::Text("DRAFT".into()),
+            SqlParam::Text("ARCHIVED".into()),
+            SqlParam::Text("REJECTED".into()),
+            SqlParam::Date("2025-01-01".into()),
+            SqlParam::Date("2026-01-01".into()),
+            SqlParam::Date("2025-01-01".into()),
+            SqlParam::Date("2025-12-31".into()),
+            SqlParam::Text("KR".into()),
+            SqlParam::Text("JP".into()),
+            SqlParam::Text("50\\%\\_off%".into()),
+            SqlParam::Text("%o'brien%".into()),
+        ]
+    );
+}
+
+/// `ilike` takes a LIKE pattern: unescaped `%` and `_` are wildcards, and `\` makes the next
+/// character literal, so exact, prefix and substring matches can all be asked for.
+#[test]
+fn ilike_patterns_keep_wildcards_and_escape_literals() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let patterns = [
+        ("summer sale", "summer sale"),
+        ("50\\%%", "50\\%%"),
+        ("%a\\_b_", "%a\\_b_"),
+        ("c:\\\\temp%", "c:\\\\temp%"),
+        ("\\x", "x"),
+        ("trailing\\", "trailing\\\\"),
+    ];
+    for (value, expected) in patterns {
+        let spec = spec(json!(["offer_id"]), json!([{ "field": "campaign_name", "op": "ilike", "value": value }]));
+        let plan = compile(&registry, &spec).expect("compile report spec");
+        assert_eq!(plan.filters[1].expression, "c.name ILIKE $2");
+        assert_eq!(plan.params, [SqlParam::Text(expected.into())], "{value}");
+    }
+}
+
+#[test]
+fn compile_resolves_relative_dates_against_the_clock() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = spec(
+        json!(["offer_id"]),
+        json!([
+            { "field": "offer_start_date", "op": "relative", "value": "last_30_days" },
+            { "field": "offer_start_date", "op": "relative", "value": "this_quarter" },
+            { "field": "offer_start_date", "op": "relative", "value": "last_month" },
+            { "field": "offer_start_date", "op": "relative", "value": "this_week" },
+            { "field": "offer_end_date", "op": "relative", "value": "before_now" }
+        ]),
+    );
+
+    // Sunday 2025-06-15
+    let ctx = CompileContext::for_profile("main").with_clock(FixedClock("2025-06-15T12:00:00Z".parse().unwrap()));
+    let plan = compile_report_spec(&registry, &spec, &ctx).expect("compile report spec");
+
+    let filters: Vec<&str> = plan.filters.iter().map(|f| f.expression.as_str()).collect();
+    assert_eq!(
+        filters,
+        [
+            "o.deleted = false",
+            "(o.start_date >= $2 AND o.start_date < $3)",
+            "(o.start_date >= $4 AND o.start_date < $5)",
+            "(o.start_date >= $6 AND o.start_date < $7)",
+            "(o.start_date >= $8 AND o.start_date < $9)",
+            "o.end_date < $10",
+        ]
+    );
+    let date = |d: &str| SqlParam::Date(d.into());
+    assert_eq!(
+        plan.params,
+        [
+            date("2025-05-17"),
+            date("2025-06-16"),
+            date("2025-04-01"),
+            date("2025-07-01"),
+            date("2025-05-01"),
+            date("2025-06-01"),
+            date("2025-06-09"),
+            date("2025-06-16"),
+            SqlParam::Timestamp("2025-06-15T12:00:00Z".into()),
+        ]
+    );
+}
+
+#[test]
+fn negated_relative_ranges_stay_grouped() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = spec(
+        json!(["offer_id"]),
+        json!([
+            { "not": { "field": "offer_start_date", "op": "relative", "value": "last_month" } },
+            { "not": { "all": [{ "field": "offer_start_date", "op": "relative", "value": "this_week" }] } }
+        ]),
+    );
+
+    let ctx = CompileContext::for_profile("main").with_clock(FixedClock("2025-06-15T12:00:00Z".parse().unwrap()));
+    let plan = compile_report_spec(&registry, &spec, &ctx).expect("compile report spec");
+    let filters: Vec<&str> = plan.filters.iter().map(|f| f.expression.as_str()).collect();
+    assert_eq!(
+        filters[1..],
+        ["NOT (o.start_date >= $2 AND o.start_date < $3)", "NOT (o.start_date >= $4 AND o.start_date < $5)"]
+    );
+}
+
+/// A single-child group renders as its child, so `not` must still parenthesize it.
+#[test]
+fn not_parenthesizes_single_child_groups() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = spec(
+        json!(["offer_id"]),
+        json!([
+            { "not": { "all": [
+                { "field": "offer_end_date", "op": "between", "value": ["2025-01-01", "2025-12-31"] }
+            ] } },
+            { "not": { "any": [
+                { "field": "workflow_status", "op": "eq", "value": "DRAFT" },
+                { "field": "workflow_status", "op": "eq", "value": "ARCHIVED" }
+            ] } },
+            { "not": { "all": [{ "all": [
+                { "field": "offer_name", "op": "eq", "value": "A" },
+                { "field": "offer_end_date", "op": "is_null" }
+            ] }] } }
+        ]),
+    );
+
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    let filters: Vec<&str> = plan.filters.iter().map(|f| f.expression.as_str()).collect();
+    assert_eq!(filters[1], "NOT (o.end_date BETWEEN $2 AND $3)");
+    assert_eq!(filters[2], "NOT (o.status = $4 OR o.status = $5)");
+    assert_eq!(filters[3], "NOT (o.name = $6 AND o.end_date IS NULL)");
+}
+
+/// `only` keeps deleted root rows; joined tables are left unfiltered and exists subqueries
+/// still drop deleted rows, so "deleted offers in a live campaign" stays expressible.
+#[test]
+fn only_deleted_applies_to_the_root_table() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let mut spec = spec(json!(["offer_id", "campaign_name"]), json!([{ "exists": { "entity": "products_latest" } }]));
+    spec.deleted = DeletedMode::Only;
+
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    let filters: Vec<&str> = plan.filters.iter().map(|f| f.expression.as_str()).collect();
+    assert_eq!(filters[0], "o.deleted = true");
+    assert!(filters[1].contains("pr.deleted = false"), "{}", filters[1]);
+    assert!(!filters[1].contains("= true"), "{}", filters[1]);
+    for join in &plan.joins {
+        assert!(join.predicates.is_empty(), "{}: {:?}", join.right_alias, join.predicates);
+    }
+}
+
+fn offers_filtered_by_phase() -> ReportSpec {
+    spec(json!(["offer_id", "offer_name"]), json!([{ "field": "promo_type", "op": "eq", "value": "PREPAID" }]))
+}
+
+/// offer_phases is 1:n from offers_latest and nothing selects a phase field, so every
+/// offer comes back once per PREPAID phase.
+#[test]
+fn compile_warns_when_a_join_fans_out_projected_rows() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let plan = compile(&registry, &offers_filtered_by_phase()).expect("compile report spec");
+
+    assert_eq!(plan.warnings.len(), 1, "{:?}", plan.warnings);
+    let warning = &plan.warnings[0];
+    assert_eq!((warning.code, warning.severity, warning.pointer.as_str()), ("fan_out", Severity::Warning, "/select/0"));
+    assert!(warning.message.starts_with("joining offer_phases to offers_latest (1:n)"), "{}", warning.message);
+    assert!(warning.message.contains("duplicating offer_id, offer_name"), "{}", warning.message);
+}
+
+#[test]
+fn compile_rejects_fan_out_under_error_policy() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let ctx = CompileContext::for_profile("main").with_fan_out(FanOutPolicy::Error);
+    let err = compile_report_spec(&registry, &offers_filtered_by_phase(), &ctx).unwrap_err();
+
+    assert!(err.to_string().contains("joining offer_phases to offers_latest (1:n)"), "{err}");
+}
+
+/// Grouping by a campaign field puts the grain on the far side of campaign_offers, so
+/// counting offers per campaign counts each pair once.
+#[test]
+fn compile_does_not_warn_when_the_grain_covers_the_many_side() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = spec(
+        json!([
+            "campaign_name",
+            { "field": "offer_id", "agg": "count" },
+            { "field": "offer_start_date", "agg": "min" }
+        ]),
+        json!([]),
+    );
+    let ctx = CompileContext::for_profile("main").with_fan_out(FanOutPolicy::Error);
+    let plan = compile_report_spec(&registry, &spec, &ctx).expect("compile report spec");
+
+    assert!(plan.warnings.is_empty(), "{:?}", plan.warnings);
+}
+
+#[test]
+fn compile_with_diagnostics_collects_every_validation_error() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = spec(
+        json!(["offer_nme", { "field": "offer_id", "agg": "sum" }]),
+        json!([{ "any": [
+            { "field": "workflow_status", "op": "eq", "value": "PUBLISHED" },
+            { "field": "offer_start_date", "op": "starts_with", "value": "2025" }
+        ] }]),
+    );
+
+    let diagnostics = compile_with_diagnostics(&registry, &spec, &CompileContext::for_profile("main")).unwrap_err();
+    let found: Vec<(&str, &str)> = diagnostics.iter().map(|d| (d.code, d.pointer.as_str())).collect();
+    assert_eq!(
+        found,
+        [
+            ("unknown_field", "/select/0/field"),
+            ("invalid_aggregate", "/select/1/agg"),
+            ("invalid_operator", "/filters/0/any/1/op"),
+        ]
+    );
+    assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+}
+
+#[test]
+fn compile_with_diagnostics_locates_pagination_errors() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.pagination = serde_json::from_value(serde_json::json!({ "limit": 10_000_000 })).unwrap();
+
+    let diagnostics = compile_with_diagnostics(&registry, &spec, &CompileContext::for_profile("main")).unwrap_err();
+    assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+    assert_eq!((diagnostics[0].code, diagnostics[0].pointer.as_str()), ("limit_exceeds_max", "/pagination/limit"));
+    assert!(diagnostics[0].suggestion.is_some());
+}
+
+/// A campaign joined to itself twice, through `parent_id` and `previous_id`. Each role is
+/// its own table instance; both ask for the alias `pc`, so the second gets `pc1`.
+#[test]
+fn compile_joins_an_entity_once_per_role() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    for (role, column) in [("parent_campaign", "parent_id"), ("previous_campaign", "previous_id")] {
+        registry.cards.join_graph.edges.push(JoinEdge {
+            from: "campaigns_latest".into(),
+            to: "campaigns_latest".into(),
+            join_type: "left".into(),
+            on: vec![
+                format!("{}.id = campaigns_latest.{}", role, column),
+                format!("{}.profile = campaigns_latest.profile", role),
+            ],
+            cardinality: "n:1".into(),
+            safe: true,
+            version_rule: VersionRule::Unchecked,
+            notes: vec![],
+            role: Some(role.into()),
+        });
+        registry.cards.field_catalog.push(FieldCard {
+            name: format!("{}_name", role),
+            entity: "campaigns_latest".into(),
+            column: Some("name".into()),
+            field_type: FieldType::String,
+            selectable: true,
+            filterable: true,
+            sortable: true,
+            role: Some(role.into()),
+            ..Default::default()
+        });
+    }
+
+    let spec = spec(json!(["campaign_name", "parent_campaign_name", "previous_campaign_name"]), json!([]));
+
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    let tables: Vec<(&str, &str, Option<&str>)> =
+        plan.tables.iter().map(|t| (t.name.as_str(), t.alias.as_str(), t.role.as_deref())).collect();
+    assert_eq!(
+        tables,
+        [
+            ("campaigns_latest", "c", None),
+            ("campaigns_latest", "pc", Some("parent_campaign")),
+            ("campaigns_latest", "pc1", Some("previous_campaign")),
+        ]
+    );
+    insta::assert_snapshot!(render_sql_inline(&plan).expect("render"));
+}
+
+fn json_path(path: &str, data_type: &str) -> JsonPathCard {
+    JsonPathCard {
+        column: "attributes".into(),
+        path: path.into(),
+        data_type: data_type.into(),
+        description: String::new(),
+        field: None,
+    }
+}
+
+#[test]
+fn compile_renders_fields_generated_from_json_paths() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    with_entity(&mut registry, "offers_latest", |offers| {
+        offers.json_paths.extend([
+            json_path("$.priority", "number"),
+            json_path("$.flags.featured", "boolean"),
+            json_path("$.launchDate", "date"),
+            json_path("$.tags", "string_array"),
+        ])
+    });
+    registry.cards.expand_json_paths().expect("expand json paths");
+
+    let mut spec = spec(
+        json!(["offer_id", "priority", "launch_date", "tags"]),
+        json!([
+            { "field": "flags_featured", "op": "eq", "value": true },
+            { "field": "priority", "op": "gte", "value": 5 },
+            { "field": "tags", "op": "overlaps", "value": ["summer"] }
+        ]),
+    );
+    spec.order_by.push(serde_json::from_value(json!({ "field": "launch_date", "dir": "desc" })).unwrap());
+
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    insta::assert_snapshot!(render_sql_inline(&plan).expect("render"));
+}
+
+/// The `::jsonb` cast follows the column card's data_type, and keys are quoted safely.
+#[test]
+fn json_paths_cast_non_jsonb_columns_and_escape_keys() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    with_entity(&mut registry, "offers_latest", |offers| {
+        offers.json_paths.push(JsonPathCard { field: Some("partner_note".into()), ..json_path("$.partner's.note", "string") })
+    });
+    registry.cards.expand_json_paths().expect("expand json paths");
+    let spec = spec(json!(["partner_note", "package_id"]), json!([]));
+
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    let projections: Vec<&str> = plan.projections.iter().map(|p| p.expression.as_str()).collect();
+    assert_eq!(projections, ["o.attributes #>> '{partner''s,note}'", "o.attributes ->> 'packageId'"]);
+
+    with_entity(&mut registry, "offers_latest", |offers| {
+        offers.columns.iter_mut().find(|c| c.name == "attributes").expect("attributes").data_type = "json".into()
+    });
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    let projections: Vec<&str> = plan.projections.iter().map(|p| p.expression.as_str()).collect();
+    assert_eq!(
+        projections,
+        ["o.attributes::jsonb #>> '{partner''s,note}'", "o.attributes::jsonb ->> 'packageId'"]
+    );
+}
+
+#[test]
+fn hand_declared_fields_take_precedence_over_json_paths() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    registry.cards.expand_json_paths().expect("expand json paths");
+
+    // package_id and promo_type are declared in the catalog, so nothing is generated.
+    let json_fields: Vec<&str> = registry
+        .cards
+        .field_catalog
+        .iter()
+        .filter(|f| f.json_path.is_some())
+        .map(|f| f.name.as_str())
+        .collect();
+    assert_eq!(json_fields, ["package_id", "promo_type"]);
+
+    with_entity(&mut registry, "offers_latest", |offers| offers.json_paths.push(json_path("$.offerName", "string")));
+    let err = registry.cards.expand_json_paths().unwrap_err();
+    assert!(err.to_string().contains("would be field 'offer_name', which already exists"), "{err}");
+}
+
+#[test]
+fn compile_expands_named_filters_from_entity_cards() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = spec(
+        json!(["offer_id"]),
+        json!([
+            { "named": "countries_any_of", "params": { "countries": ["AU", "NZ"] } },
+            { "exists": { "entity": "offer_phases", "filters": [{ "named": "phase_type_prepaid" }] } }
+        ]),
+    );
+
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    let filters: Vec<&str> = plan.filters.iter().map(|f| f.expression.as_str()).collect();
+    assert_eq!(filters[1], "o.countries && ARRAY[$2, $3]");
+    assert_eq!(plan.params, [SqlParam::Text("AU".into()), SqlParam::Text("NZ".into())]);
+    insta::assert_snapshot!(render_sql_inline(&plan).expect("render"));
+}
+
+/// Each `:name` slot is bound as the type its hint declares, and the hint's SQL is kept as
+/// written apart from the qualifiers.
+#[test]
+fn named_filter_params_are_bound_as_their_declared_types() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    with_entity(&mut registry, "offers_latest", |offers| {
+        offers.common_filters.push(FilterHint {
+            name: "newer_than".into(),
+            sql: "version > :min_version and start_date::date >= :since".into(),
+            description: String::new(),
+            params: vec![
+                FilterParam { name: "min_version".into(), param_type: FieldType::Number },
+                FilterParam { name: "since".into(), param_type: FieldType::Date },
+            ],
+        })
+    });
+    registry.cards.check_common_filters().expect("check common filters");
+    let spec = spec(
+        json!(["offer_id"]),
+        json!([{ "named": "newer_than", "params": { "min_version": 3, "since": "2025-01-01" } }]),
+    );
+
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    assert_eq!(plan.filters[1].expression, "(o.version > $2 and o.start_date::date >= $3)");
+    assert_eq!(plan.params, [SqlParam::Int(3), SqlParam::Date("2025-01-01".into())]);
+
+    with_entity(&mut registry, "offers_latest", |offers| {
+        offers.common_filters.last_mut().unwrap().sql = "version > :min_version AND end_date >= :until".into()
+    });
+    let err = registry.cards.check_common_filters().unwrap_err();
+    assert!(format!("{err:#}").contains("parameter ':until' is not declared in params"), "{err:#}");
+
+    with_entity(&mut registry, "offers_latest", |offers| {
+        offers.common_filters.last_mut().unwrap().sql = "version > :min_version".into()
+    });
+    let err = registry.cards.check_common_filters().unwrap_err();
+    assert!(format!("{err:#}").contains("param since is never used as :since"), "{err:#}");
+}
+
+#[test]
+fn common_filters_may_only_read_their_entity() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    with_entity(&mut registry, "offers_latest", |offers| {
+        offers.common_filters[0].sql = "offers_latest.status = 'LIVE' AND offer_phases.legacy IS NOT NULL".into()
+    });
+    let err = registry.cards.check_common_filters().unwrap_err();
+    assert!(format!("{err:#}").contains("'offer_phases.legacy' is not a column of offers_latest"), "{err:#}");
+
+    with_entity(&mut registry, "offers_latest", |offers| {
+        offers.common_filters[0].sql = "start_date <= now() AND not_a_column = 1".into()
+    });
+    let err = registry.cards.check_common_filters().unwrap_err();
+    assert!(format!("{err:#}").contains("'not_a_column' is not a column of offers_latest"), "{err:#}");
+}
+
+fn derived(name: &str, sql: &str, depends_on: &[&str]) -> DerivedField {
+    DerivedField {
+        name: name.into(),
+        sql: sql.into(),
+        depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+/// Adds `field` with a selectable, sortable catalog entry on offers_latest.
+fn add_derived_field(registry: &mut SchemaRegistry, field: DerivedField) {
+    registry.cards.field_catalog.push(FieldCard {
+        name: field.name.clone(),
+        entity: "offers_latest".into(),
+        field_type: field.result_type,
+        selectable: true,
+        filterable: false,
+        sortable: true,
+        ..Default::default()
+    });
+    registry.cards.derived_fields.push(field);
+}
+
+#[test]
+fn compile_rewrites_derived_field_qualifiers_on_the_syntax_tree() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    add_derived_field(
+        &mut registry,
+        derived(
+            "offer_label",
+            "offers_latest.name || ' (from offers_latest.name)'  ||  offers_latest.end_date::text",
+            &["offers_latest.name", "offers_latest.end_date"],
+        ),
+    );
+    registry.cards.check_derived_fields().expect("check derived fields");
+
+    let mut spec = spec(json!(["offer_label"]), json!([]));
+    spec.order_by.push(serde_json::from_value(json!({ "field": "offer_label", "dir": "asc" })).unwrap());
+
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    // Only the qualifiers change; casing and spacing are kept as the card wrote them.
+    assert_eq!(plan.projections[0].expression, "o.name || ' (from offers_latest.name)'  ||  o.end_date::text");
+    assert_eq!(plan.order_by[0].expression, plan.projections[0].expression);
+}
+
+#[test]
+fn derived_fields_may_only_read_their_dependencies() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    registry.cards.derived_fields.push(derived(
+        "offer_window",
+        "offers_latest.end_date - offers_latest.start_date",
+        &["offers_latest.end_date"],
+    ));
+    let err = registry.cards.check_derived_fields().unwrap_err();
+    assert!(format!("{err:#}").contains("reads offers_latest.start_date without listing it in depends_on"), "{err:#}");
+
+    registry.cards.derived_fields.last_mut().unwrap().sql = "end_date - offers_latest.start_date".into();
+    let err = registry.cards.check_derived_fields().unwrap_err();
+    assert!(
+        format!("{err:#}").contains("'end_date' is neither a column qualified by its entity nor a derived field"),
+        "{err:#}"
+    );
+
+    registry.cards.derived_fields.last_mut().unwrap().sql = "offers_latest.ends_at".into();
+    registry.cards.derived_fields.last_mut().unwrap().depends_on = vec!["offers_latest.ends_at".into()];
+    let err = registry.cards.check_derived_fields().unwrap_err();
+    assert!(err.to_string().contains("depends on unknown column offers_latest.ends_at"), "{err}");
+}
+
+#[test]
+fn compile_expands_composed_derived_fields() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    let mut expiring_this_week = derived("expiring_this_week", "is_live AND expires_within(7)", &["is_live", "expires_within"]);
+    expiring_this_week.result_type = FieldType::Bool;
+    add_derived_field(&mut registry, expiring_this_week);
+    registry.cards.check_derived_fields().expect("check derived fields");
+
+    let spec = spec(json!(["offer_id", "expires_within", "expiring_this_week"]), json!([]));
+
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    let projections: Vec<&str> = plan.projections.iter().map(|p| p.expression.as_str()).collect();
+    assert_eq!(
+        projections,
+        [
+            "o.id",
+            "(o.end_date::date - CURRENT_DATE) BETWEEN 0 AND 30",
+            "((CASE WHEN o.end_date::date < CURRENT_DATE THEN 'EXPIRED' ELSE o.status END) <> 'EXPIRED') \
+             AND ((o.end_date::date - CURRENT_DATE) BETWEEN 0 AND 7)",
+        ]
+    );
+}
+
+/// A filter's `args` are bound as typed params; params it leaves out use their defaults.
+#[test]
+fn derived_field_params_bind_filter_args() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = spec(
+        json!(["offer_id"]),
+        json!([
+            { "field": "expires_within", "args": { "days": 7 }, "op": "eq", "value": true },
+            { "not": { "field": "expires_within", "op": "eq", "value": true } }
+        ]),
+    );
+
+    let plan = compile(&registry, &spec).expect("compile report spec");
+    let filters: Vec<&str> = plan.filters.iter().skip(1).map(|f| f.expression.as_str()).collect();
+    assert_eq!(
+        filters,
+        [
+            "((o.end_date::date - CURRENT_DATE) BETWEEN 0 AND $2) = $3",
+            "NOT (((o.end_date::date - CURRENT_DATE) BETWEEN 0 AND 30) = $4)",
+        ]
+    );
+    assert_eq!(plan.params, [SqlParam::Int(7), SqlParam::Bool(true), SqlParam::Bool(true)]);
+}
+
+#[test]
+fn derived_field_args_are_checked_against_its_params() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let compile_with_args = |args: serde_json::Value| {
+        let spec = spec(json!(["offer_id"]), json!([{ "field": "expires_within", "args": args, "op": "eq", "value": true }]));
+        compile(&registry, &spec).unwrap_err().to_string()
+    };
+
+    let err = compile_with_args(json!({ "days": "7" }));
+    assert!(err.contains("invalid args for field 'expires_within': 'days' must be a number"), "{err}");
+    let err = compile_with_args(json!({ "weeks": 1 }));
+    assert!(err.contains("invalid args for field 'expires_within': unknown arg 'weeks'"), "{err}");
+}
+
+#[test]
+fn derived_field_calls_are_checked() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    registry.cards.derived_fields.push(derived("soon", "expires_within('7')", &["expires_within"]));
+    let err = registry.cards.check_derived_fields().unwrap_err();
+    assert!(format!("{err:#}").contains("argument days of derived field expires_within must be a Number literal"), "{err:#}");
+
+    registry.cards.derived_fields.last_mut().unwrap().sql = "expires_within(7, 8)".into();
+    let err = registry.cards.check_derived_fields().unwrap_err();
+    assert!(format!("{err:#}").contains("derived field expires_within takes 1 arguments, got 2"), "{err:#}");
+
+    registry.cards.derived_fields.last_mut().unwrap().sql = "expires_within(:weeks)".into();
+    let err = registry.cards.check_derived_fields().unwrap_err();
+    assert!(format!("{err:#}").contains("parameter ':weeks' is not one of its params"), "{err:#}");
+
+    // A caller may pass its own param on, if the types agree.
+    let soon = registry.cards.derived_fields.last_mut().unwrap();
+    soon.params = vec![DerivedParam { name: "weeks".into(), param_type: FieldType::String, default: None }];
+    let err = registry.cards.check_derived_fields().unwrap_err();
+    assert!(format!("{err:#}").contains("argument days of derived field expires_within must be a Number literal or param"), "{err:#}");
+    registry.cards.derived_fields.last_mut().unwrap().params[0].param_type = FieldType::Number;
+    registry.cards.check_derived_fields().expect("check derived fields");
+
+    registry.cards.derived_fields.last_mut().unwrap().sql = "expires_within(7)".into();
+    let err = registry.cards.check_derived_fields().unwrap_err();
+    assert!(format!("{err:#}").contains("param weeks is never used as :weeks"), "{err:#}");
+}
+
+#[test]
+fn derived_field_cycles_are_rejected() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    add_derived_field(&mut registry, derived("ping", "pong", &["pong"]));
+    add_derived_field(&mut registry, derived("pong", "ping", &["ping"]));
+    let err = registry.cards.check_derived_fields().unwrap_err();
+    assert_eq!(err.to_string(), "derived fields form a cycle: ping -> pong -> ping");
+
+    // The compiler guards against cycles too, for cards that were never checked.
+    let err = compile(&registry, &spec(json!(["ping"]), json!([]))).unwrap_err();
+    assert!(err.to_string().contains("derived fields form a cycle: ping -> pong -> ping"), "{err}");
+}
+
+#[test]
+fn aggregate_derived_fields_cannot_be_marked_filterable() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    with_field(&mut registry, "products_csv", |card| card.filterable = true);
+    let err = registry.cards.check_derived_fields().unwrap_err();
+    assert!(err.to_string().contains("products_csv aggregates rows, so it can only be filtered in having"), "{err}");
+}
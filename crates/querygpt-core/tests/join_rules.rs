@@ -0,0 +1,118 @@
+use querygpt_core::dsl::compile::{compile_report_spec, CompileContext};
+use querygpt_core::dsl::join_rules::{check_join_rules, JoinRuleError};
+use querygpt_core::dsl::plan::{IntermediatePlan, JoinCondition, JoinType, PlanJoin, PlanTable};
+
+mod common;
+
+use crate::common::{load_fixture, load_schema_registry};
+
+fn offers_to_campaign_offers(conditions: Vec<JoinCondition>) -> IntermediatePlan {
+    IntermediatePlan {
+        workspace: "campaigns_offers".into(),
+        tables: vec![
+            PlanTable::new("offers_latest", "o"),
+            PlanTable::new("campaign_offers", "co"),
+        ],
+        joins: vec![PlanJoin {
+            left_alias: "o".into(),
+            right_alias: "co".into(),
+            join_type: JoinType::Inner,
+            conditions,
+            ..Default::default()
+        }],
+        projections: vec![],
+        filters: vec![],
+        group_by: vec![],
+        having: vec![],
+        rank: None,
+        order_by: vec![],
+        limit: None,
+        offset: None,
+        profile_scope: None,
+        params: vec![],
+        warnings: vec![],
+    }
+}
+
+fn cond(left: &str, right: &str) -> JoinCondition {
+    JoinCondition { left_field: left.into(), right_field: right.into() }
+}
+
+#[test]
+fn compiled_plan_satisfies_join_rules() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = load_fixture("campaigns_offers_prepaid_apac.json");
+
+    let plan = compile_report_spec(&registry, &spec, &CompileContext::for_profile("main")).expect("compile report spec");
+    assert_eq!(check_join_rules(&plan, &registry.cards), vec![]);
+}
+
+#[test]
+fn rejects_matching_campaign_offers_version_to_offer_version() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let plan = offers_to_campaign_offers(vec![
+        cond("o.id", "co.offer_id"),
+        cond("o.profile", "co.profile"),
+        cond("o.version", "co.version"),
+    ]);
+
+    assert_eq!(
+        check_join_rules(&plan, &registry.cards),
+        vec![JoinRuleError::ForbiddenVersion {
+            from: "offers_latest".into(),
+            to: "campaign_offers".into(),
+            column: "version".into(),
+        }]
+    );
+}
+
+#[test]
+fn rejects_join_without_profile() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let plan = offers_to_campaign_offers(vec![cond("o.id", "co.offer_id")]);
+
+    assert_eq!(
+        check_join_rules(&plan, &registry.cards),
+        vec![JoinRuleError::MissingProfile {
+            from: "offers_latest".into(),
+            to: "campaign_offers".into(),
+            column: "profile".into(),
+        }]
+    );
+}
+
+#[test]
+fn rejects_version_aligned_join_without_version() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let mut plan = offers_to_campaign_offers(vec![]);
+    plan.tables[1] = PlanTable::new("offer_products", "opr");
+    plan.joins[0].right_alias = "opr".into();
+    plan.joins[0].conditions = vec![cond("o.id", "opr.offer_id"), cond("o.profile", "opr.profile")];
+
+    assert_eq!(
+        check_join_rules(&plan, &registry.cards),
+        vec![JoinRuleError::MissingVersion {
+            from: "offers_latest".into(),
+            to: "offer_products".into(),
+            column: "version".into(),
+        }]
+    );
+}
+
+#[test]
+fn rejects_edge_marked_unsafe() {
+    let mut registry = load_schema_registry("campaigns_offers.index.json");
+    registry
+        .cards
+        .join_graph
+        .edges
+        .iter_mut()
+        .filter(|e| e.to == "campaign_offers")
+        .for_each(|e| e.safe = false);
+    let plan = offers_to_campaign_offers(vec![cond("o.id", "co.offer_id"), cond("o.profile", "co.profile")]);
+
+    assert_eq!(
+        check_join_rules(&plan, &registry.cards),
+        vec![JoinRuleError::UnsafeEdge { from: "offers_latest".into(), to: "campaign_offers".into() }]
+    );
+}
@@ -1,9 +1,12 @@
 use insta::assert_snapshot;
-use querygpt_core::dsl::compile::compile_report_spec;
+use querygpt_core::dsl::compile::{compile_report_spec, CompileContext};
+use querygpt_core::dsl::dates::FixedClock;
+use querygpt_core::dsl::fan_out::FanOutPolicy;
+use querygpt_core::dsl::plan::SqlParam;
 
-use querygpt_core::dsl::report_spec::{PaginationSpec, ReportSpec};
+use querygpt_core::dsl::report_spec::{DeletedMode, Mode, PaginationSpec, ReportSpec};
 use querygpt_core::schema::registry::SchemaRegistry;
-use querygpt_core::sql::render::render_sql;
+use querygpt_core::sql::render::{render_sql, render_sql_inline};
 
 
 fn repo_path(rel: &str) -> String {
@@ -16,7 +19,7 @@ fn repo_path(rel: &str) -> String {
 }
 
 fn test_registry() -> SchemaRegistry {
-    SchemaRegistry::load(&repo_path("config/workspaces/campaigns_offers.index.json"))
+    SchemaRegistry::load(repo_path("config/workspaces/campaigns_offers.index.json"))
         .expect("load SchemaRegistry")
 }
 
@@ -34,8 +37,8 @@ fn load_spec_json(name: &str) -> ReportSpec {
 
 fn compile_and_render(spec: ReportSpec) -> String {
     let reg = test_registry();
-    let plan = compile_report_spec(&reg, &spec).expect("compile failed");
-    render_sql(&plan).expect("render failed")
+    let plan = compile_report_spec(&reg, &spec, &CompileContext::for_profile("main")).expect("compile failed");
+    render_sql_inline(&plan).expect("render failed")
 }
 
 #[test]
@@ -54,3 +57,297 @@ fn pipeline_sql_prepaid_apac_export_pagination() {
     assert_snapshot!("pipeline_sql__prepaid_apac_export_pagination", sql);
 }
 
+#[test]
+fn pipeline_sql_prepaid_apac_preview_default_limit() {
+    let mut spec = load_spec_json("base");
+    spec.mode = Mode::Preview;
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__prepaid_apac_preview_default_limit", sql);
+}
+
+#[test]
+fn pipeline_sql_prepaid_apac_preview_limit_is_clamped() {
+    let mut spec = load_spec_json("base");
+    spec.mode = Mode::Preview;
+    spec.pagination = Some(PaginationSpec { limit: Some(50_000), offset: Some(10) });
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__prepaid_apac_preview_limit_is_clamped", sql);
+}
+
+#[test]
+fn pipeline_rejects_export_limit_above_workspace_max() {
+    let mut spec = load_spec_json("base");
+    spec.pagination = Some(PaginationSpec { limit: Some(5_000_000), offset: None });
+
+    let reg = test_registry();
+    let err = compile_report_spec(&reg, &spec, &CompileContext::for_profile("main")).unwrap_err();
+    assert!(err.to_string().contains("exceeds the workspace maximum"));
+}
+
+#[test]
+fn pipeline_sql_prepaid_apac_include_deleted() {
+    let mut spec = load_spec_json("base");
+    spec.deleted = DeletedMode::Include;
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__prepaid_apac_include_deleted", sql);
+}
+
+#[test]
+fn pipeline_sql_prepaid_apac_only_deleted() {
+    let mut spec = load_spec_json("base");
+    spec.deleted = DeletedMode::Only;
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__prepaid_apac_only_deleted", sql);
+}
+
+#[test]
+fn pipeline_sql_prepaid_apac_boolean_filters() {
+    let mut spec = load_spec_json("base");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "field": "promo_type", "op": "eq", "value": "PREPAID" },
+        { "any": [
+            { "field": "workflow_status", "op": "eq", "value": "PUBLISHED" },
+            { "field": "workflow_status", "op": "eq", "value": "SCHEDULED" }
+        ] },
+        { "not": { "field": "countries", "op": "overlaps", "value": ["KR"] } }
+    ]))
+    .expect("parse filters");
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__prepaid_apac_boolean_filters", sql);
+}
+
+#[test]
+fn pipeline_sql_prepaid_apac_parameterized() {
+    let spec = load_spec_json("base");
+    let reg = test_registry();
+    let plan = compile_report_spec(&reg, &spec, &CompileContext::for_profile("main")).expect("compile failed");
+    let rendered = render_sql(&plan).expect("render failed");
+
+    let params: Vec<String> = rendered.params.iter().map(SqlParam::to_sql_literal).collect();
+    assert_eq!(
+        params,
+        ["'main'", "'PREPAID'", "'KR'", "'JP'", "'TW'", "'SG'", "'HK'", "'PUBLISHED'", "'EXPIRED'"]
+    );
+    assert_snapshot!("pipeline_sql__prepaid_apac_parameterized", rendered.sql);
+}
+
+#[test]
+fn pipeline_sql_offer_counts_by_status() {
+    let spec: ReportSpec = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "workspace": "campaigns_offers",
+        "select": [
+            { "field": "workflow_status" },
+            { "field": "offer_id", "agg": "count_distinct", "alias": "offers" },
+            { "field": "offer_start_date", "agg": "min", "alias": "first_start" },
+            { "field": "campaign_name", "agg": "string_agg", "alias": "campaigns" }
+        ],
+        "group_by": ["workflow_status"],
+        "order_by": [{ "field": "workflow_status", "dir": "asc" }],
+        "mode": "export"
+    }))
+    .expect("parse spec");
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__offer_counts_by_status", sql);
+}
+
+#[test]
+fn pipeline_sql_campaigns_with_many_live_offers() {
+    let spec: ReportSpec = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "workspace": "campaigns_offers",
+        "select": [
+            { "field": "campaign_id" },
+            { "field": "campaign_name" },
+            { "field": "offer_id", "agg": "count_distinct", "alias": "live_offers" }
+        ],
+        "filters": [
+            { "field": "workflow_status", "op": "eq", "value": "PUBLISHED" }
+        ],
+        "having": [
+            { "field": "offer_id", "agg": "count_distinct", "op": "gt", "value": 5 }
+        ],
+        "order_by": [{ "field": "campaign_id", "dir": "asc" }],
+        "mode": "export"
+    }))
+    .expect("parse spec");
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__campaigns_with_many_live_offers", sql);
+}
+
+#[test]
+fn pipeline_sql_offers_expiring_per_month() {
+    let spec: ReportSpec = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "workspace": "campaigns_offers",
+        "select": [
+            { "field": "offer_end_date", "date_trunc": "month", "alias": "month" },
+            { "field": "offer_id", "agg": "count_distinct", "alias": "expiring" }
+        ],
+        "filters": [
+            { "field": "offer_end_date", "op": "relative", "value": "next_6_months" }
+        ],
+        "order_by": [{ "field": "offer_end_date", "dir": "asc" }],
+        "mode": "export"
+    }))
+    .expect("parse spec");
+
+    let now = "2025-06-15T12:00:00Z".parse().unwrap();
+    let ctx = CompileContext::for_profile("main").with_clock(FixedClock(now));
+    let plan = compile_report_spec(&test_registry(), &spec, &ctx).expect("compile failed");
+    let sql = render_sql_inline(&plan).expect("render failed");
+    assert_snapshot!("pipeline_sql__offers_expiring_per_month", sql);
+}
+
+#[test]
+fn pipeline_sql_latest_offers_per_campaign() {
+    let spec: ReportSpec = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "workspace": "campaigns_offers",
+        "select": [
+            { "field": "campaign_name" },
+            { "field": "offer_name" },
+            { "field": "offer_start_date", "alias": "starts" }
+        ],
+        "rank": {
+            "partition_by": ["campaign_id"],
+            "order_by": [{ "field": "offer_start_date", "dir": "desc" }],
+            "top_n": 3
+        },
+        "order_by": [{ "field": "campaign_name", "dir": "asc" }],
+        "mode": "export"
+    }))
+    .expect("parse spec");
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__latest_offers_per_campaign", sql);
+}
+
+#[test]
+fn pipeline_sql_rank_partners_by_offer_count() {
+    let spec: ReportSpec = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "workspace": "campaigns_offers",
+        "select": [
+            { "field": "partnership_id" },
+            { "field": "offer_id", "agg": "count_distinct", "alias": "offers" }
+        ],
+        "rank": {
+            "order_by": [
+                { "field": "offer_id", "agg": "count_distinct", "dir": "desc" },
+                { "field": "partnership_id", "dir": "asc" }
+            ],
+            "alias": "partner_rank"
+        },
+        "pagination": { "limit": 10 }
+    }))
+    .expect("parse spec");
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__rank_partners_by_offer_count", sql);
+}
+
+#[test]
+fn pipeline_sql_offers_without_products() {
+    let spec: ReportSpec = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "workspace": "campaigns_offers",
+        "select": [{ "field": "offer_id" }, { "field": "offer_name" }],
+        "filters": [{ "not_exists": { "entity": "offer_products" } }],
+        "order_by": [{ "field": "offer_id", "dir": "asc" }],
+        "mode": "export"
+    }))
+    .expect("parse spec");
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__offers_without_products", sql);
+}
+
+#[test]
+fn pipeline_sql_campaigns_with_a_prepaid_phase() {
+    let spec: ReportSpec = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "workspace": "campaigns_offers",
+        "select": [{ "field": "campaign_id" }, { "field": "campaign_name" }],
+        "filters": [{
+            "exists": {
+                "entity": "offer_phases",
+                "filters": [{ "field": "promo_type", "op": "eq", "value": "PREPAID" }]
+            }
+        }],
+        "order_by": [{ "field": "campaign_name", "dir": "asc" }],
+        "mode": "export"
+    }))
+    .expect("parse spec");
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__campaigns_with_a_prepaid_phase", sql);
+}
+
+#[test]
+fn pipeline_sql_prepaid_offers_pre_aggregated() {
+    let spec: ReportSpec = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "workspace": "campaigns_offers",
+        "select": [{ "field": "offer_id" }, { "field": "offer_name" }],
+        "filters": [
+            { "field": "promo_type", "op": "eq", "value": "PREPAID" },
+            { "field": "workflow_status", "op": "eq", "value": "PUBLISHED" }
+        ],
+        "order_by": [{ "field": "offer_id", "dir": "asc" }],
+        "mode": "export"
+    }))
+    .expect("parse spec");
+
+    let reg = test_registry();
+    let ctx = CompileContext::for_profile("main").with_fan_out(FanOutPolicy::PreAggregate);
+    let plan = compile_report_spec(&reg, &spec, &ctx).expect("compile failed");
+    assert!(plan.warnings.is_empty(), "{:?}", plan.warnings);
+
+    let sql = render_sql_inline(&plan).expect("render failed");
+    assert_snapshot!("pipeline_sql__prepaid_offers_pre_aggregated", sql);
+}
+
+#[test]
+fn pipeline_sql_expired_offers_by_derived_status() {
+    let spec: ReportSpec = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "workspace": "campaigns_offers",
+        "select": [{ "field": "offer_id" }, { "field": "days_until_expiry" }],
+        "filters": [
+            { "field": "expired_or_live_status", "op": "eq", "value": "EXPIRED" },
+            { "any": [
+                { "field": "is_live", "op": "eq", "value": false },
+                { "field": "expires_within", "args": { "days": 14 }, "op": "eq", "value": true }
+            ] }
+        ],
+        "order_by": [{ "field": "days_until_expiry", "dir": "asc" }],
+        "mode": "export"
+    }))
+    .expect("parse spec");
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__expired_offers_by_derived_status", sql);
+}
+
+#[test]
+fn pipeline_sql_offers_having_a_product() {
+    let spec: ReportSpec = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "workspace": "campaigns_offers",
+        "select": [{ "field": "offer_id" }, { "field": "products_csv" }],
+        "having": [{ "field": "products_csv", "op": "ilike", "value": "%PRD-1%" }],
+        "mode": "export"
+    }))
+    .expect("parse spec");
+
+    let sql = compile_and_render(spec);
+    assert_snapshot!("pipeline_sql__offers_having_a_product", sql);
+}
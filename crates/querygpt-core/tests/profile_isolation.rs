@@ -0,0 +1,70 @@
+use querygpt_core::dsl::compile::{compile_report_spec, CompileContext};
+use querygpt_core::policy::rules::enforce_profile_isolation;
+use querygpt_core::sql::render::render_sql;
+
+mod common;
+
+use crate::common::{load_fixture, load_schema_registry};
+
+#[test]
+fn compiled_plan_is_isolated_on_root_profile() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = load_fixture("campaigns_offers_prepaid_apac.json");
+
+    let plan = compile_report_spec(&registry, &spec, &CompileContext::for_profile("main"))
+        .expect("compile report spec");
+
+    let scope = plan.profile_scope.as_ref().expect("profile scope");
+    assert_eq!(scope.alias, "o");
+    assert_eq!(scope.column, "profile");
+    assert_eq!(scope.profiles, ["main"]);
+    assert!(render_sql(&plan).unwrap().sql.contains("WHERE o.profile = $1\n"));
+}
+
+#[test]
+fn profile_set_renders_any() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = load_fixture("campaigns_offers_prepaid_apac.json");
+
+    let plan = compile_report_spec(&registry, &spec, &CompileContext::for_profiles(["main", "kids"]))
+        .expect("compile report spec");
+
+    assert!(render_sql(&plan).unwrap().sql.contains("WHERE o.profile = ANY($1)\n"));
+}
+
+#[test]
+fn compile_requires_a_profile() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = load_fixture("campaigns_offers_prepaid_apac.json");
+
+    let err = compile_report_spec(&registry, &spec, &CompileContext::for_profiles(Vec::<String>::new()))
+        .unwrap_err();
+    assert!(err.to_string().contains("at least one profile"));
+}
+
+#[test]
+fn policy_refuses_plan_without_profile_predicate() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = load_fixture("campaigns_offers_prepaid_apac.json");
+
+    let mut plan = compile_report_spec(&registry, &spec, &CompileContext::for_profile("main"))
+        .expect("compile report spec");
+    enforce_profile_isolation(&plan).expect("compiled plan passes policy");
+
+    plan.profile_scope = None;
+    let err = enforce_profile_isolation(&plan).unwrap_err();
+    assert!(err.to_string().contains("no profile isolation predicate"));
+}
+
+#[test]
+fn policy_refuses_profile_predicate_off_root() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = load_fixture("campaigns_offers_prepaid_apac.json");
+
+    let mut plan = compile_report_spec(&registry, &spec, &CompileContext::for_profile("main"))
+        .expect("compile report spec");
+    plan.profile_scope.as_mut().unwrap().alias = "p".into();
+
+    let err = enforce_profile_isolation(&plan).unwrap_err();
+    assert!(err.to_string().contains("rooted at 'o'"));
+}
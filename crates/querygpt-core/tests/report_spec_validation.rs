@@ -1,13 +1,15 @@
-use std::fs;
+use querygpt_core::dsl::report_spec::normalize;
+use querygpt_core::dsl::validate::{check_report_spec, validate_report_spec, SpecError};
+use querygpt_core::schema::field_catalog::WorkspaceSchema;
+use querygpt_core::schema::workspaces::workspace_schema;
 
-use querygpt_core::dsl::report_spec::{normalize, ReportSpec};
-use querygpt_core::dsl::validate::validate_report_spec;
-use querygpt_core::schema::workspaces::campaigns_offers_schema;
+mod common;
 
-fn load_fixture(name: &str) -> ReportSpec {
-    let path = format!("tests/fixtures/report_specs/{}", name);
-    let s = fs::read_to_string(path).expect("fixture read");
-    serde_json::from_str::<ReportSpec>(&s).expect("fixture parse")
+use crate::common::{load_fixture, load_schema_registry};
+
+fn campaigns_offers_schema() -> WorkspaceSchema {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    workspace_schema(&registry, "campaigns_offers").expect("campaigns_offers workspace schema")
 }
 
 #[test]
@@ -23,6 +25,8 @@ fn rejects_unknown_field_in_select() {
     spec.select.push(querygpt_core::dsl::report_spec::SelectItem {
         field: "does_not_exist".into(),
         alias: None,
+        agg: None,
+        date_trunc: None,
     });
 
     let ws = campaigns_offers_schema();
@@ -35,11 +39,14 @@ fn rejects_unknown_field_in_select() {
 fn rejects_invalid_op_overlaps_on_string() {
     let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
     // Make a bad filter: overlaps on promo_type (enum, filterable but overlaps not valid)
-    spec.filters.push(querygpt_core::dsl::report_spec::Filter {
-        field: "promo_type".into(),
-        op: querygpt_core::dsl::report_spec::FilterOp::Overlaps,
-        value: serde_json::json!(["x"]),
-    });
+    spec.filters.push(querygpt_core::dsl::report_spec::FilterExpr::Predicate(
+        querygpt_core::dsl::report_spec::Filter {
+            field: "promo_type".into(),
+            op: querygpt_core::dsl::report_spec::FilterOp::Overlaps,
+            value: serde_json::json!(["x"]),
+            args: Default::default(),
+        },
+    ));
 
     let ws = campaigns_offers_schema();
     let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
@@ -53,3 +60,510 @@ fn snapshot_normalized_spec() {
     let normalized = normalize(spec);
     insta::assert_json_snapshot!(normalized);
 }
+
+#[test]
+fn workspace_schema_is_none_for_other_workspace() {
+    let registry = load_schema_registry("campaigns_offers.index.json");
+    let spec = load_fixture("campaigns_offers_prepaid_apac.json");
+
+    let ws = workspace_schema(&registry, "genie_prod");
+    let err = validate_report_spec(&spec, ws.as_ref()).unwrap_err();
+    assert!(err.to_string().contains("workspace 'campaigns_offers' not found"));
+}
+
+#[test]
+fn validates_nested_filter_groups() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "any": [
+            { "field": "workflow_status", "op": "eq", "value": "PUBLISHED" },
+            { "field": "workflow_status", "op": "eq", "value": "SCHEDULED" }
+        ] },
+        { "not": { "field": "countries", "op": "overlaps", "value": ["KR"] } }
+    ]))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    validate_report_spec(&spec, Some(&ws)).expect("should validate");
+}
+
+#[test]
+fn rejects_invalid_predicate_inside_group() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "not": { "any": [
+            { "field": "workflow_status", "op": "eq", "value": "PUBLISHED" },
+            { "field": "partnership_id", "op": "eq", "value": "x" }
+        ] } }
+    ]))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("field 'partnership_id' is not filterable"));
+}
+
+#[test]
+fn rejects_empty_filter_group() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([{ "any": [] }])).unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("must not be empty"));
+}
+
+#[test]
+fn normalize_orders_nested_groups_canonically() {
+    let mut a = load_fixture("campaigns_offers_prepaid_apac.json");
+    a.filters = serde_json::from_value(serde_json::json!([
+        { "field": "workflow_status", "op": "eq", "value": "PUBLISHED" },
+        { "any": [
+            { "field": "workflow_status", "op": "eq", "value": "SCHEDULED" },
+            { "field": "countries", "op": "overlaps", "value": ["KR"] }
+        ] }
+    ]))
+    .unwrap();
+
+    let mut b = a.clone();
+    b.filters.reverse();
+    if let querygpt_core::dsl::report_spec::FilterExpr::Any { any } = &mut b.filters[0] {
+        any.reverse();
+    }
+
+    assert_eq!(normalize(a), normalize(b));
+}
+
+#[test]
+fn rejects_between_without_two_bounds() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "field": "offer_end_date", "op": "between", "value": ["2025-01-01"] }
+    ]))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("expected [low, high] for 'between'"), "{err}");
+}
+
+#[test]
+fn rejects_value_on_null_check() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "field": "offer_end_date", "op": "is_null", "value": "2025-01-01" }
+    ]))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("'is_null' takes no value"), "{err}");
+}
+
+#[test]
+fn rejects_pattern_operator_on_array_field() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "field": "countries", "op": "ilike", "value": "K" }
+    ]))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("invalid operator"), "{err}");
+}
+
+#[test]
+fn rejects_sum_on_string_field() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.select = serde_json::from_value(serde_json::json!([
+        { "field": "offer_name", "agg": "sum" }
+    ]))
+    .unwrap();
+    spec.order_by.clear();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("invalid aggregate 'Sum' for field 'offer_name'"), "{err}");
+}
+
+#[test]
+fn rejects_aggregate_of_aggregate_field() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.select = serde_json::from_value(serde_json::json!([
+        { "field": "products_csv", "agg": "count" }
+    ]))
+    .unwrap();
+    spec.order_by.clear();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("field 'products_csv' is already an aggregate"), "{err}");
+}
+
+#[test]
+fn rejects_select_field_missing_from_group_by() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.select = serde_json::from_value(serde_json::json!([
+        { "field": "workflow_status" },
+        { "field": "offer_name" },
+        { "field": "offer_id", "agg": "count" }
+    ]))
+    .unwrap();
+    spec.group_by = vec!["workflow_status".into()];
+    spec.order_by.clear();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("field 'offer_name' in select must be in group_by or aggregated"), "{err}");
+}
+
+#[test]
+fn rejects_order_by_on_ungrouped_field() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.select = serde_json::from_value(serde_json::json!([
+        { "field": "workflow_status" },
+        { "field": "offer_id", "agg": "count" }
+    ]))
+    .unwrap();
+    spec.order_by = serde_json::from_value(serde_json::json!([{ "field": "offer_id", "dir": "asc" }])).unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("field 'offer_id' in order_by must be in group_by or aggregated"), "{err}");
+}
+
+#[test]
+fn rejects_having_on_unaggregated_field() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.having = serde_json::from_value(serde_json::json!([
+        { "field": "workflow_status", "op": "eq", "value": "PUBLISHED" }
+    ]))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("having on field 'workflow_status' must reference an aggregated select item"), "{err}");
+}
+
+#[test]
+fn rejects_having_on_aggregate_not_selected() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.having = serde_json::from_value(serde_json::json!([
+        { "field": "offer_id", "agg": "count", "op": "gt", "value": 5 }
+    ]))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("having on field 'offer_id' must reference an aggregated select item"), "{err}");
+}
+
+#[test]
+fn validates_having_value_against_aggregate_type() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.select = serde_json::from_value(serde_json::json!([
+        { "field": "workflow_status" },
+        { "field": "offer_id", "agg": "count" },
+        { "field": "products_csv" }
+    ]))
+    .unwrap();
+    spec.order_by.clear();
+    spec.having = serde_json::from_value(serde_json::json!([
+        { "field": "products_csv", "op": "ilike", "value": "PRD-1" },
+        { "field": "offer_id", "agg": "count", "op": "gte", "value": "five" }
+    ]))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("invalid value for field 'offer_id': expected number"), "{err}");
+}
+
+#[test]
+fn rejects_unknown_relative_date() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "field": "offer_end_date", "op": "relative", "value": "last_0_days" }
+    ]))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("expected a relative date"), "{err}");
+}
+
+#[test]
+fn rejects_date_trunc_on_non_date_field() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.select = serde_json::from_value(serde_json::json!([
+        { "field": "offer_name", "date_trunc": "month" }
+    ]))
+    .unwrap();
+    spec.order_by.clear();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("date_trunc on field 'offer_name' requires an unaggregated date field"), "{err}");
+}
+
+#[test]
+fn rejects_rank_order_by_on_unselected_field() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.select = serde_json::from_value(serde_json::json!([{ "field": "offer_name" }])).unwrap();
+    spec.rank = serde_json::from_value(serde_json::json!({
+        "partition_by": ["campaign_id"],
+        "order_by": [{ "field": "offer_start_date", "dir": "desc" }]
+    }))
+    .unwrap();
+    spec.order_by = serde_json::from_value(serde_json::json!([{ "field": "offer_id", "dir": "asc" }])).unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("invalid rank: order_by field 'offer_id' must be selected"), "{err}");
+}
+
+#[test]
+fn rejects_rank_with_zero_top_n() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.rank = serde_json::from_value(serde_json::json!({
+        "order_by": [{ "field": "offer_id", "dir": "asc" }],
+        "top_n": 0
+    }))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("invalid rank: top_n must be at least 1"), "{err}");
+}
+
+#[test]
+fn rejects_rank_partition_on_ungrouped_field() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.select = serde_json::from_value(serde_json::json!([
+        { "field": "partnership_id" },
+        { "field": "offer_id", "agg": "count" }
+    ]))
+    .unwrap();
+    spec.order_by.clear();
+    spec.rank = serde_json::from_value(serde_json::json!({
+        "partition_by": ["campaign_id"],
+        "order_by": [{ "field": "offer_id", "agg": "count", "dir": "desc" }]
+    }))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("field 'campaign_id' in rank must be in group_by or aggregated"), "{err}");
+}
+
+#[test]
+fn rejects_exists_on_unknown_entity() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "exists": { "entity": "offer_regions" } }
+    ]))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("unknown entity 'offer_regions' in exists"), "{err}");
+}
+
+#[test]
+fn rejects_exists_filter_on_field_of_another_entity() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "exists": {
+            "entity": "offer_products",
+            "filters": [{ "field": "promo_type", "op": "eq", "value": "PREPAID" }]
+        } }
+    ]))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(err.to_string().contains("promo_type"), "{err}");
+}
+
+#[test]
+fn check_reports_every_error_with_its_location() {
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.select.truncate(2);
+    spec.order_by.truncate(1);
+    spec.group_by = vec!["products_csv".to_string()];
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "field": "countries", "op": "overlaps", "value": [] },
+        { "not": { "all": [] } },
+        { "not_exists": {
+            "entity": "offer_products",
+            "filters": [{ "field": "promo_type", "op": "eq", "value": "PREPAID" }]
+        } }
+    ]))
+    .unwrap();
+
+    let ws = campaigns_offers_schema();
+    let diagnostics = check_report_spec(&spec, Some(&ws));
+    let found: Vec<(&str, &str)> = diagnostics.iter().map(|d| (d.code, d.pointer.as_str())).collect();
+    assert_eq!(
+        found,
+        [
+            ("already_aggregated", "/group_by/0"),
+            ("invalid_value", "/filters/0/value"),
+            ("empty_filter_group", "/filters/1/not/all"),
+            ("field_outside_exists", "/filters/2/not_exists/filters/0/field"),
+            ("not_grouped", "/select/0/field"),
+            ("not_grouped", "/select/1/field"),
+            ("not_grouped", "/order_by/0/field"),
+        ]
+    );
+    assert_eq!(diagnostics[4].suggestion.as_deref(), Some("add 'partnership_id' to group_by or aggregate it"));
+}
+
+#[test]
+fn check_accepts_example_spec() {
+    let spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    let ws = campaigns_offers_schema();
+    assert_eq!(check_report_spec(&spec, Some(&ws)), vec![]);
+}
+
+fn unknown_field_suggestions(err: SpecError) -> Vec<String> {
+    match err {
+        SpecError::UnknownField { suggestions, .. } => suggestions,
+        other => panic!("expected UnknownField, got {other}"),
+    }
+}
+
+#[test]
+fn suggests_fields_for_misspelled_names() {
+    let ws = campaigns_offers_schema();
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.select[4].field = "offer_nme".to_string();
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert_eq!(unknown_field_suggestions(err).first().map(String::as_str), Some("offer_name"));
+
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters[1] = serde_json::from_value(serde_json::json!(
+        { "field": "countrys", "op": "overlaps", "value": ["KR"] }
+    ))
+    .unwrap();
+    let diagnostics = check_report_spec(&spec, Some(&ws));
+    assert_eq!(diagnostics[0].pointer, "/filters/1/field");
+    assert_eq!(diagnostics[0].suggestion.as_deref(), Some("did you mean 'countries'?"));
+}
+
+#[test]
+fn suggests_fields_by_synonym_and_column_name() {
+    let ws = campaigns_offers_schema();
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "field": "phase_type", "op": "eq", "value": "PREPAID" },
+        { "field": "end_date", "op": "relative", "value": "before_now" }
+    ]))
+    .unwrap();
+
+    let diagnostics = check_report_spec(&spec, Some(&ws));
+    let suggestions: Vec<Option<&str>> = diagnostics.iter().map(|d| d.suggestion.as_deref()).collect();
+    assert_eq!(suggestions[0], Some("did you mean 'promo_type'?"));
+    assert!(suggestions[1].is_some_and(|s| s.starts_with("did you mean 'offer_end_date'")), "{suggestions:?}");
+}
+
+#[test]
+fn suggests_only_fields_usable_in_context() {
+    let ws = campaigns_offers_schema();
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    // offer_id is not filterable, so it must not be offered for a filter
+    spec.filters[0] = serde_json::from_value(serde_json::json!(
+        { "field": "offer_idd", "op": "eq", "value": "x" }
+    ))
+    .unwrap();
+
+    let err = validate_report_spec(&spec, Some(&ws)).unwrap_err();
+    assert!(!unknown_field_suggestions(err).contains(&"offer_id".to_string()));
+}
+
+#[test]
+fn suggests_entities_for_exists() {
+    let ws = campaigns_offers_schema();
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "not_exists": { "entity": "offer_product" } }
+    ]))
+    .unwrap();
+
+    let diagnostics = check_report_spec(&spec, Some(&ws));
+    assert_eq!(diagnostics[0].pointer, "/filters/0/not_exists/entity");
+    assert_eq!(diagnostics[0].suggestion.as_deref(), Some("did you mean 'offer_products'?"));
+}
+
+#[test]
+fn accepts_named_filters_with_their_params() {
+    let ws = campaigns_offers_schema();
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "named": "phase_type_prepaid" },
+        { "named": "countries_any_of", "params": { "countries": ["AU", "NZ"] } },
+        { "named": "profile_main", "entity": "offers_latest" }
+    ]))
+    .unwrap();
+
+    assert_eq!(check_report_spec(&spec, Some(&ws)), vec![]);
+}
+
+#[test]
+fn check_locates_named_filter_errors() {
+    let ws = campaigns_offers_schema();
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "named": "phase_type_prepaidd" },
+        { "named": "profile_main" },
+        { "named": "countries_any_of" },
+        { "named": "countries_any_of", "params": { "countries": [], "region": "APAC" } },
+        { "exists": { "entity": "offer_products", "filters": [{ "named": "phase_type_prepaid" }] } },
+        { "named": "countries_any_of", "params": { "countries": "KR" } }
+    ]))
+    .unwrap();
+
+    let diagnostics = check_report_spec(&spec, Some(&ws));
+    let found: Vec<(&str, &str)> = diagnostics.iter().map(|d| (d.code, d.pointer.as_str())).collect();
+    assert_eq!(
+        found,
+        [
+            ("unknown_named_filter", "/filters/0/named"),
+            ("ambiguous_named_filter", "/filters/1/named"),
+            ("invalid_filter_params", "/filters/2/params"),
+            ("invalid_filter_params", "/filters/3/params"),
+            ("named_filter_outside_exists", "/filters/4/exists/filters/0/named"),
+            ("invalid_filter_params", "/filters/5/params"),
+        ]
+    );
+    let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(messages[2], "invalid params for named filter 'countries_any_of': missing 'countries'");
+    assert_eq!(messages[3], "invalid params for named filter 'countries_any_of': unknown param 'region'");
+    assert_eq!(
+        messages[5],
+        "invalid params for named filter 'countries_any_of': 'countries' must be a non-empty array of strings"
+    );
+    assert_eq!(diagnostics[0].suggestion.as_deref(), Some("did you mean 'phase_type_prepaid'?"));
+    assert_eq!(
+        diagnostics[1].suggestion.as_deref(),
+        Some("set 'entity' to one of 'offers_latest', 'campaigns_latest'")
+    );
+}
+
+#[test]
+fn filters_on_derived_fields_follow_the_catalog() {
+    let ws = campaigns_offers_schema();
+    let mut spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    spec.filters = serde_json::from_value(serde_json::json!([
+        { "field": "expired_or_live_status", "op": "eq", "value": "EXPIRED" },
+        { "field": "is_live", "op": "eq", "value": "yes" },
+        { "field": "products_csv", "op": "ilike", "value": "PRD-1" }
+    ]))
+    .unwrap();
+
+    let diagnostics = check_report_spec(&spec, Some(&ws));
+    let found: Vec<(&str, &str)> = diagnostics.iter().map(|d| (d.code, d.pointer.as_str())).collect();
+    assert_eq!(found, [("invalid_value", "/filters/1/value"), ("aggregate_in_filter", "/filters/2/field")]);
+    assert_eq!(diagnostics[1].suggestion.as_deref(), Some("select 'products_csv' and filter on it in having"));
+}
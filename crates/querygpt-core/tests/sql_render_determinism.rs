@@ -1,4 +1,7 @@
+use querygpt_core::dsl::compile::{compile_report_spec, CompileContext};
 use querygpt_core::dsl::plan::{IntermediatePlan, PlanTable, PlanJoin, JoinCondition, JoinType};
+use querygpt_core::dsl::report_spec::ReportSpec;
+use querygpt_core::schema::registry::SchemaRegistry;
 use querygpt_core::sql::render::render_sql;
 
 #[test]
@@ -7,8 +10,8 @@ fn renderer_is_deterministic_given_same_plan() {
     let plan_a = IntermediatePlan {
         workspace: "campaigns_offers".to_string(),
         tables: vec![
-            PlanTable { name: "offers_latest".into(), alias: "o".into() },
-            PlanTable { name: "campaign_offers".into(), alias: "co".into() },
+            PlanTable::new("offers_latest", "o"),
+            PlanTable::new("campaign_offers", "co"),
         ],
         joins: vec![
             PlanJoin {
@@ -19,25 +22,54 @@ fn renderer_is_deterministic_given_same_plan() {
                     JoinCondition { left_field: "o.id".into(), right_field: "co.offer_id".into() },
                     JoinCondition { left_field: "o.profile".into(), right_field: "co.profile".into() },
                 ],
+                ..Default::default()
             }
         ],
-        projections: vec![],
-        filters: vec![],
-        order_by: vec![],
-        limit: None,
-        offset: None,
+        ..Default::default()
     };
 
     let plan_b = IntermediatePlan {
         tables: vec![
-            PlanTable { name: "campaign_offers".into(), alias: "co".into() },
-            PlanTable { name: "offers_latest".into(), alias: "o".into() },
+            PlanTable::new("campaign_offers", "co"),
+            PlanTable::new("offers_latest", "o"),
         ],
         ..plan_a.clone()
     };
 
-    let sql_a = render_sql(&plan_a).expect("render A");
-    let sql_b = render_sql(&plan_b).expect("render B");
+    let sql_a = render_sql(&plan_a).expect("render A").sql;
+    let sql_b = render_sql(&plan_b).expect("render B").sql;
 
     assert_eq!(sql_a, sql_b, "SQL should be identical for semantically identical plans");
 }
+
+/// Ties on the rank's own keys (two offers starting the same day) must not let ROW_NUMBER
+/// or the output order vary between runs: the window ends with the root's primary key, and
+/// the outer query keeps partitions together before ordering by rank.
+#[test]
+fn ranked_sql_breaks_ties_deterministically() {
+    let registry = SchemaRegistry::load("../../config/workspaces/campaigns_offers.index.json").expect("load registry");
+    let spec: ReportSpec = serde_json::from_value(serde_json::json!({
+        "version": 1,
+        "workspace": "campaigns_offers",
+        "select": [{ "field": "campaign_name" }, { "field": "offer_name" }],
+        "rank": {
+            "partition_by": ["campaign_id"],
+            "order_by": [{ "field": "offer_start_date", "dir": "desc" }],
+            "top_n": 1
+        },
+        "mode": "export"
+    }))
+    .expect("parse spec");
+
+    let plan = compile_report_spec(&registry, &spec, &CompileContext::for_profile("main")).expect("compile");
+    let rank = plan.rank.as_ref().expect("rank");
+    let window: Vec<&str> = rank.order_by.iter().map(|o| o.expression.as_str()).collect();
+    assert_eq!(window, ["o.start_date", "o.id", "o.profile"]);
+    let outer: Vec<&str> = plan.order_by.iter().map(|o| o.expression.as_str()).collect();
+    assert_eq!(outer, ["rank_key_1", "rank", "rank_key_2", "rank_key_3"]);
+
+    let mut reordered = plan.clone();
+    reordered.tables.reverse();
+    reordered.joins.reverse();
+    assert_eq!(render_sql(&plan).expect("render").sql, render_sql(&reordered).expect("render").sql);
+}
@@ -1,10 +1,13 @@
 
 use std::path::PathBuf;
-use querygpt_core::dsl::compile::compile_report_spec;
-use querygpt_core::dsl::plan::{IntermediatePlan, JoinCondition, JoinType, PlanJoin, PlanOrder, PlanProjection, PlanTable, SortDirection};
+use querygpt_core::dsl::compile::{compile_report_spec, CompileContext};
+use querygpt_core::dsl::plan::{
+    IntermediatePlan, JoinCondition, JoinType, PlanFilter, PlanGroupBy, PlanJoin, PlanOrder, PlanProfileScope, PlanProjection,
+    PlanTable, SortDirection, SqlParam,
+};
 use querygpt_core::dsl::report_spec::ReportSpec;
 use querygpt_core::schema::registry::SchemaRegistry;
-use querygpt_core::sql::render::render_sql;
+use querygpt_core::sql::render::{render_sql, render_sql_inline};
 fn repo_root_from_crate() -> PathBuf {
     // crates/querygpt-core -> repo root (two levels up)
     let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -23,7 +26,7 @@ fn normalize_sql(s: &str) -> String {
 
 #[test]
 fn full_query_snapshot_from_reportspec() {
-    let reg = SchemaRegistry::load(&repo_path("config/workspaces/campaigns_offers.index.json"))
+    let reg = SchemaRegistry::load(repo_path("config/workspaces/campaigns_offers.index.json"))
         .expect("load SchemaRegistry");
 
 
@@ -31,8 +34,8 @@ fn full_query_snapshot_from_reportspec() {
     let spec: ReportSpec = serde_json::from_str(spec_str).expect("parse ReportSpec");
 
 
-    let plan = compile_report_spec(&reg, &spec).expect("compile plan");
-    let sql = render_sql(&plan).expect("render sql");
+    let plan = compile_report_spec(&reg, &spec, &CompileContext::for_profile("main")).expect("compile plan");
+    let sql = render_sql_inline(&plan).expect("render sql");
 
     insta::assert_snapshot!(normalize_sql(&sql));
 }
@@ -42,8 +45,8 @@ fn full_query_with_group_by_and_order_by() {
     let plan = IntermediatePlan {
         workspace: "campaigns_offers".into(),
         tables: vec![
-            PlanTable { name: "offers_latest".into(), alias: "o".into() },
-            PlanTable { name: "offer_products".into(), alias: "opr".into() },
+            PlanTable::new("offers_latest", "o"),
+            PlanTable::new("offer_products", "opr"),
         ],
         joins: vec![
             PlanJoin {
@@ -55,6 +58,7 @@ fn full_query_with_group_by_and_order_by() {
                     JoinCondition { left_field: "o.profile".into(), right_field: "opr.profile".into() },
                     JoinCondition { left_field: "o.version".into(), right_field: "opr.version".into() },
                 ],
+                ..Default::default()
             }
         ],
         projections: vec![
@@ -65,14 +69,44 @@ fn full_query_with_group_by_and_order_by() {
                 alias: None,
             },
         ],
-        filters: vec![],
+        group_by: vec![PlanGroupBy { field: "offer_id".into(), expression: "o.id".into() }],
         order_by: vec![
             PlanOrder { expression: "o.id".into(), direction: SortDirection::Asc },
         ],
-        limit: None,
-        offset: None,
+        ..Default::default()
     };
 
-    let sql = render_sql(&plan).unwrap();
+    let sql = render_sql(&plan).unwrap().sql;
     insta::assert_snapshot!(sql);
 }
+
+/// Only placeholders Postgres binds are inlined; `$n` in quoted identifiers and strings,
+/// dollar-quoted strings and comments stays as written.
+#[test]
+fn inline_sql_keeps_dollar_digits_outside_placeholders() {
+    let plan = IntermediatePlan {
+        workspace: "campaigns_offers".into(),
+        tables: vec![PlanTable::new("offers_latest", "o")],
+        projections: vec![PlanProjection {
+            field: "label".into(),
+            expression: "o.\"col$1\" || '$2' || $tag$ $2 $tag$ || $$it's $1$$ /* $2 */".into(),
+            alias: None,
+        }],
+        filters: vec![PlanFilter { expression: "o.name = $2 -- not $1\n".into() }],
+        profile_scope: Some(PlanProfileScope {
+            alias: "o".into(),
+            column: "profile".into(),
+            profiles: vec!["main".into()],
+        }),
+        params: vec![SqlParam::Text("it's".into())],
+        ..Default::default()
+    };
+
+    let sql = render_sql_inline(&plan).expect("render sql");
+    assert!(
+        sql.contains("o.\"col$1\" || '$2' || $tag$ $2 $tag$ || $$it's $1$$ /* $2 */"),
+        "{sql}"
+    );
+    assert!(sql.contains("o.name = 'it''s' -- not $1\n"), "{sql}");
+    assert!(sql.contains("o.profile = 'main'"), "{sql}");
+}
@@ -1,4 +1,4 @@
-use querygpt_core::dsl::plan::{IntermediatePlan, JoinCondition, JoinType, PlanJoin, PlanProjection, PlanTable};
+use querygpt_core::dsl::plan::{IntermediatePlan, JoinCondition, JoinType, PlanGroupBy, PlanJoin, PlanProjection, PlanTable};
 use querygpt_core::sql::render::render_sql;
 
 #[test]
@@ -6,8 +6,8 @@ fn group_by_added_when_aggregate_present() {
     let plan = IntermediatePlan {
         workspace: "campaigns_offers".into(),
         tables: vec![
-            PlanTable { name: "offers_latest".into(), alias: "o".into() },
-            PlanTable { name: "offer_products".into(), alias: "opr".into() },
+            PlanTable::new("offers_latest", "o"),
+            PlanTable::new("offer_products", "opr"),
         ],
         joins: vec![
             PlanJoin {
@@ -19,18 +19,17 @@ fn group_by_added_when_aggregate_present() {
                     JoinCondition { left_field: "o.profile".into(), right_field: "opr.profile".into() },
                     JoinCondition { left_field: "o.version".into(), right_field: "opr.version".into() },
                 ],
+                ..Default::default()
             }
         ],
         projections: vec![
             PlanProjection { field: "offer_id".into(), expression: "o.id".into(), alias: None },
             PlanProjection { field: "products_csv".into(), expression: "STRING_AGG(DISTINCT opr.product_id, ',')".into(), alias: None },
         ],
-        filters: vec![],
-        order_by: vec![],
-        limit: None,
-        offset: None,
+        group_by: vec![PlanGroupBy { field: "offer_id".into(), expression: "o.id".into() }],
+        ..Default::default()
     };
 
-    let sql = render_sql(&plan).unwrap();
+    let sql = render_sql(&plan).unwrap().sql;
     insta::assert_snapshot!(sql);
 }
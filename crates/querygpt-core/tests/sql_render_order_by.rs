@@ -6,21 +6,18 @@ fn order_by_renders_in_plan_order() {
     let plan = IntermediatePlan {
         workspace: "campaigns_offers".into(),
         tables: vec![
-            PlanTable { name: "offers_latest".into(), alias: "o".into() },
+            PlanTable::new("offers_latest", "o"),
         ],
-        joins: vec![],
         projections: vec![
             PlanProjection { field: "offer_id".into(), expression: "o.id".into(), alias: None },
         ],
-        filters: vec![],
         order_by: vec![
             PlanOrder { expression: "o.id".into(), direction: SortDirection::Asc },
             PlanOrder { expression: "o.name".into(), direction: SortDirection::Desc },
         ],
-        limit: None,
-        offset: None,
+        ..Default::default()
     };
 
-    let sql = render_sql(&plan).unwrap();
+    let sql = render_sql(&plan).unwrap().sql;
     insta::assert_snapshot!(sql);
 }
@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use querygpt_core::schema::cards::DerivedField;
+use querygpt_core::schema::field_catalog::FieldType;
+use querygpt_core::schema::registry::WorkspaceCatalog;
+
+mod common;
+
+use crate::common::{load_fixture, load_schema_registry, with_derived_field};
+
+fn workspaces_dir() -> PathBuf {
+    let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    crate_dir
+        .parent().and_then(|p| p.parent())
+        .map(|p| p.join("config/workspaces"))
+        .expect("resolve config/workspaces from CARGO_MANIFEST_DIR")
+}
+
+#[test]
+fn loads_every_index_in_config_dir() {
+    let catalog = WorkspaceCatalog::load_dir(workspaces_dir()).expect("load catalog");
+
+    assert_eq!(catalog.workspaces().collect::<Vec<_>>(), ["campaigns_offers"]);
+    let reg = catalog.get("campaigns_offers").expect("campaigns_offers registry");
+    let direct = load_schema_registry("campaigns_offers.index.json");
+    assert_eq!(reg.cards.workspace, direct.cards.workspace);
+    assert_eq!(reg.cards.field_catalog.len(), direct.cards.field_catalog.len());
+}
+
+#[test]
+fn looks_up_registry_by_spec_workspace() {
+    let catalog = WorkspaceCatalog::load_dir(workspaces_dir()).expect("load catalog");
+
+    let spec = load_fixture("campaigns_offers_prepaid_apac.json");
+    let reg = catalog.registry_for(&spec).expect("registry for spec");
+    assert_eq!(reg.index.workspace, spec.workspace);
+
+    let mut other = spec.clone();
+    other.workspace = "genie_prod".into();
+    let err = catalog.registry_for(&other).unwrap_err();
+    assert!(err.to_string().contains("workspace 'genie_prod' not found"));
+}
+
+#[test]
+fn resolves_exemplar_dir_against_the_index_file() {
+    // Tests run from the crate directory, not the repo root, so this only passes when the
+    // dir is resolved relative to the index rather than the working directory.
+    let catalog = WorkspaceCatalog::load_dir(workspaces_dir()).expect("load catalog");
+    let reg = catalog.get("campaigns_offers").expect("campaigns_offers registry");
+
+    assert_eq!(reg.exemplar_dir, workspaces_dir().join("campaigns_offers/exemplars"));
+    let files = reg.exemplar_sql_files().expect("list exemplars");
+    let names: Vec<_> = files.iter().filter_map(|p| p.file_name()?.to_str()).collect();
+    assert!(names.contains(&"prepaid_apac_export.sql"), "{names:?}");
+}
+
+/// Every derived field declares its result type, and the catalog must agree with it.
+#[test]
+fn derived_fields_declare_their_result_type() {
+    let err = serde_json::from_value::<DerivedField>(serde_json::json!({
+        "name": "is_live",
+        "sql": "expired_or_live_status <> 'EXPIRED'",
+        "description": "",
+        "depends_on": ["expired_or_live_status"]
+    }))
+    .unwrap_err();
+    assert!(err.to_string().contains("missing field `result_type`"), "{err}");
+
+    let mut reg = load_schema_registry("campaigns_offers.index.json");
+    with_derived_field(&mut reg, "is_live", |is_live| is_live.result_type = FieldType::String);
+    let err = reg.cards.check_derived_fields().unwrap_err();
+    assert!(err.to_string().contains("field is_live is Bool in the catalog but its derived field returns String"), "{err}");
+}
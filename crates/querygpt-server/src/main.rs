@@ -1,7 +1,11 @@
-use axum::{routing::post, Json, Router};
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
 use serde::{Deserialize, Serialize};
-use querygpt_core::agents::intent;
-use querygpt_core::schema::registry::SchemaRegistry;
+use querygpt_core::agents::intent::{self, IntentResult};
+use querygpt_core::dsl::diagnostics::Diagnostic;
+use querygpt_core::dsl::report_spec::{DeletedMode, Mode, ReportSpec};
+use querygpt_core::schema::registry::WorkspaceCatalog;
 
 #[derive(Debug, Deserialize)]
 struct GenerateRequest {
@@ -15,16 +19,44 @@ struct GenerateResponse {
     explanation: String,
 }
 
-async fn generate(Json(req): Json<GenerateRequest>) -> Json<GenerateResponse> {
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// The spec the pipeline starts from: only the workspace and mode are known after intent.
+fn draft_spec(intent: &IntentResult) -> ReportSpec {
+    ReportSpec {
+        version: 1,
+        workspace: intent.workspace.clone(),
+        select: vec![],
+        filters: vec![],
+        group_by: vec![],
+        having: vec![],
+        rank: None,
+        order_by: vec![],
+        mode: if intent.task == "export" { Mode::Export } else { Mode::Preview },
+        pagination: None,
+        deleted: DeletedMode::default(),
+    }
+}
+
+async fn generate(
+    State(catalog): State<Arc<WorkspaceCatalog>>,
+    Json(req): Json<GenerateRequest>,
+) -> Result<Json<GenerateResponse>, (StatusCode, Json<ErrorResponse>)> {
     let intent = intent::classify(&req.user_prompt);
-    // In production: load workspace registry based on intent.workspace
-    let _reg = SchemaRegistry::load("config/workspaces/campaigns_offers.index.json").ok();
+    let spec = draft_spec(&intent);
+    let _reg = catalog.registry_for(&spec).map_err(|err| {
+        let diagnostic = Diagnostic::error("unknown_workspace", err.to_string(), "/workspace");
+        (StatusCode::NOT_FOUND, Json(ErrorResponse { diagnostics: vec![diagnostic] }))
+    })?;
 
-    Json(GenerateResponse {
-        workspace: intent.workspace,
+    Ok(Json(GenerateResponse {
+        workspace: spec.workspace,
         sql: "-- SQL generation pipeline TBD".to_string(),
         explanation: "-- Explanation TBD".to_string(),
-    })
+    }))
 }
 
 #[tokio::main]
@@ -35,9 +67,31 @@ async fn main() {
     let host = std::env::var("SERVER_HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
     let port = std::env::var("SERVER_PORT").unwrap_or_else(|_| "8080".to_string());
     let bind_addr = format!("{}:{}", host, port);
-    
-    let app = Router::new().route("/generate", post(generate));
+
+    let workspaces_dir = std::env::var("WORKSPACES_DIR").unwrap_or_else(|_| "config/workspaces".to_string());
+    let catalog = WorkspaceCatalog::load_dir(&workspaces_dir).expect("load workspace catalog");
+    tracing::info!(workspaces = ?catalog.workspaces().collect::<Vec<_>>(), "loaded workspace catalog");
+
+    let app = Router::new()
+        .route("/generate", post(generate))
+        .with_state(Arc::new(catalog));
     let listener = tokio::net::TcpListener::bind(&bind_addr).await.unwrap();
     println!("Server running on {}", bind_addr);
     axum::serve(listener, app).await.unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn generate_rejects_an_unknown_workspace() {
+        let req = GenerateRequest { user_prompt: "prepaid offers in APAC".into() };
+        let (status, Json(body)) = generate(State(Arc::new(WorkspaceCatalog::default())), Json(req)).await.unwrap_err();
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body.diagnostics[0].code, "unknown_workspace");
+        assert_eq!(body.diagnostics[0].pointer, "/workspace");
+        assert!(body.diagnostics[0].message.contains("workspace 'campaigns_offers' not found"));
+    }
+}